@@ -0,0 +1,251 @@
+//! Provides #[derive(DBusStruct)], which maps a Rust struct to a D-Bus struct.  The derive
+//! implements rustc_serialize's Encodable and Decodable along with dbus_serialize's DBusType, so
+//! the struct can be converted with DBusEncoder::encode_typed and DBusDecoder::decode_typed.
+//!
+//! Fields may be annotated with #[dbus(...)]:
+//!
+//! * rename = "name": use name as the field name given to the Encoder and Decoder.  D-Bus struct
+//!   fields are unnamed, so this only changes error paths and the output of other encoders, such
+//!   as the keys written by rustc_serialize's JSON encoder.
+//! * skip: leave the field out of the D-Bus struct.  It is set to Default::default() on decode.
+//! * object_path: send a String field as an object path ('o').  Encoding and decoding fail if the
+//!   string is not a valid object path.
+//! * signature: send a String field as a signature ('g').  Encoding and decoding fail if the
+//!   string is not a valid signature.
+//! * variant: wrap the field in a Variant ('v')
+#![allow(deprecated)]
+#![allow(clippy::redundant_field_names)]
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data,DeriveInput,Fields,GenericParam,Generics,Index,LitStr,parse_macro_input,parse_quote};
+
+/// How a field's D-Bus type is chosen
+enum FieldType {
+    Natural,
+    ObjectPath,
+    Signature,
+    Variant,
+}
+
+struct Field {
+    /// Expression to access the field on self, e.g. `self.x` or `self.0`
+    member: TokenStream2,
+    /// Identifier used when constructing the struct, or None for a tuple struct
+    ident: Option<syn::Ident>,
+    name: String,
+    ty: syn::Type,
+    field_type: FieldType,
+    skip: bool,
+}
+
+fn parse_field (index: usize, field: &syn::Field) -> syn::Result<Field> {
+    let (member, name) = match field.ident {
+        Some(ref x) => (quote!(#x), x.to_string()),
+        None => {
+            let i = Index::from(index);
+            (quote!(#i), index.to_string())
+        }
+    };
+    let mut f = Field {
+        member: member,
+        ident: field.ident.clone(),
+        name: name,
+        ty: field.ty.clone(),
+        field_type: FieldType::Natural,
+        skip: false,
+    };
+
+    for attr in field.attrs.iter() {
+        if !attr.path().is_ident("dbus") {
+            continue;
+        }
+        try!(attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                let s: LitStr = try!(try!(meta.value()).parse());
+                f.name = s.value();
+            } else if meta.path.is_ident("skip") {
+                f.skip = true;
+            } else if meta.path.is_ident("object_path") {
+                f.field_type = FieldType::ObjectPath;
+            } else if meta.path.is_ident("signature") {
+                f.field_type = FieldType::Signature;
+            } else if meta.path.is_ident("variant") {
+                f.field_type = FieldType::Variant;
+            } else {
+                return Err(meta.error("unknown dbus attribute"));
+            }
+            Ok(())
+        }));
+    }
+    Ok(f)
+}
+
+/// Wraps a Result expression in the expansion of try!.  The macro itself cannot be emitted, since
+/// try is a reserved word in crates using the 2018 edition or later.
+fn try_expr (e: TokenStream2) -> TokenStream2 {
+    quote! {
+        match #e {
+            ::std::result::Result::Ok(x) => x,
+            ::std::result::Result::Err(e) => return ::std::result::Result::Err(e)
+        }
+    }
+}
+
+fn add_bounds (generics: &Generics, bound: syn::TypeParamBound) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.params.iter_mut() {
+        if let GenericParam::Type(ref mut t) = *param {
+            t.bounds.push(bound.clone());
+        }
+    }
+    generics
+}
+
+#[proc_macro_derive(DBusStruct, attributes(dbus))]
+pub fn derive_dbus_struct (input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(x) => x.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+fn expand (input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let data = match input.data {
+        Data::Struct(ref x) => x,
+        _ => return Err(syn::Error::new_spanned(input, "DBusStruct can only be derived for structs"))
+    };
+    let mut fields = Vec::new();
+    for (i, field) in data.fields.iter().enumerate() {
+        fields.push(try!(parse_field(i, field)));
+    }
+    let sent: Vec<&Field> = fields.iter().filter(|f| !f.skip).collect();
+    if sent.is_empty() {
+        return Err(syn::Error::new_spanned(input, "D-Bus structs must have at least one field"));
+    }
+
+    let name = &input.ident;
+    let name_str = name.to_string();
+    let len = sent.len();
+
+    let signatures = sent.iter().map(|f| {
+        let ty = &f.ty;
+        match f.field_type {
+            FieldType::Natural => quote! {
+                <#ty as ::dbus_serialize::signature::DBusType>::signature()
+            },
+            FieldType::ObjectPath => quote! {
+                ::dbus_serialize::signature::SignatureType::Basic(::dbus_serialize::signature::BasicType::ObjectPath)
+            },
+            FieldType::Signature => quote! {
+                ::dbus_serialize::signature::SignatureType::Basic(::dbus_serialize::signature::BasicType::Signature)
+            },
+            FieldType::Variant => quote! {
+                ::dbus_serialize::signature::SignatureType::Variant
+            },
+        }
+    });
+
+    // The attributed fields are wrapped in the type that DBusEncoder gives the right signature,
+    // so that plain DBusEncoder::encode agrees with DBusType
+    let encodes = sent.iter().enumerate().map(|(i, f)| {
+        let member = &f.member;
+        let field_name = &f.name;
+        let value = match f.field_type {
+            FieldType::Natural => quote!(&self.#member),
+            FieldType::ObjectPath => quote! {
+                &::dbus_serialize::types::Path(::std::string::ToString::to_string(&self.#member))
+            },
+            FieldType::Signature => quote! {
+                &::dbus_serialize::types::Signature(::std::string::ToString::to_string(&self.#member))
+            },
+            FieldType::Variant => quote!(&::dbus_serialize::typed::Variant(&self.#member)),
+        };
+        let emit = try_expr(quote! {
+            s.emit_struct_field(#field_name, #i, |s| ::rustc_serialize::Encodable::encode(#value, s))
+        });
+        quote!(#emit;)
+    });
+
+    let mut idx: usize = 0;
+    let mut decodes = Vec::new();
+    for f in fields.iter() {
+        let value = if f.skip {
+            quote!(::std::default::Default::default())
+        } else {
+            let field_name = &f.name;
+            let ty = &f.ty;
+            let i = idx;
+            idx += 1;
+            let wrapper = match f.field_type {
+                FieldType::Natural => None,
+                FieldType::ObjectPath => Some(quote!(::dbus_serialize::types::Path)),
+                FieldType::Signature => Some(quote!(::dbus_serialize::types::Signature)),
+                FieldType::Variant => Some(quote!(::dbus_serialize::typed::Variant<#ty>)),
+            };
+            try_expr(match wrapper {
+                None => quote!(d.read_struct_field(#field_name, #i, ::rustc_serialize::Decodable::decode)),
+                Some(w) => quote! {
+                    d.read_struct_field(#field_name, #i, |d| {
+                        <#w as ::rustc_serialize::Decodable>::decode(d).map(|x| x.0)
+                    })
+                }
+            })
+        };
+        decodes.push(match f.ident {
+            Some(ref x) => quote!(#x: #value),
+            None => value
+        });
+    }
+    let construct = match data.fields {
+        Fields::Named(_) => quote!(#name { #(#decodes),* }),
+        _ => quote!(#name ( #(#decodes),* )),
+    };
+
+    let type_generics = add_bounds(&input.generics, parse_quote!(::dbus_serialize::signature::DBusType));
+    let (type_impl, ty, type_where) = type_generics.split_for_impl();
+    let mut enc_generics = add_bounds(&input.generics, parse_quote!(::rustc_serialize::Encodable));
+    let mut dec_generics = add_bounds(&input.generics, parse_quote!(::rustc_serialize::Decodable));
+    for f in sent.iter().filter(|f| matches!(f.field_type, FieldType::Variant)) {
+        let ty = &f.ty;
+        enc_generics.make_where_clause().predicates.push(parse_quote! {
+            for<'a> ::dbus_serialize::typed::Variant<&'a #ty>: ::rustc_serialize::Encodable
+        });
+        dec_generics.make_where_clause().predicates.push(parse_quote! {
+            ::dbus_serialize::typed::Variant<#ty>: ::rustc_serialize::Decodable
+        });
+    }
+    let (enc_impl, _, enc_where) = enc_generics.split_for_impl();
+    let (dec_impl, _, dec_where) = dec_generics.split_for_impl();
+
+    Ok(quote! {
+        impl #type_impl ::dbus_serialize::signature::DBusType for #name #ty #type_where {
+            fn signature() -> ::dbus_serialize::signature::SignatureType {
+                ::dbus_serialize::signature::SignatureType::Struct(vec![#(#signatures),*])
+            }
+        }
+
+        impl #enc_impl ::rustc_serialize::Encodable for #name #ty #enc_where {
+            fn encode<S: ::rustc_serialize::Encoder>(&self, s: &mut S) -> ::std::result::Result<(), S::Error> {
+                s.emit_struct(#name_str, #len, |s| {
+                    #(#encodes)*
+                    Ok(())
+                })
+            }
+        }
+
+        impl #dec_impl ::rustc_serialize::Decodable for #name #ty #dec_where {
+            fn decode<D: ::rustc_serialize::Decoder>(d: &mut D) -> ::std::result::Result<Self, D::Error> {
+                d.read_struct(#name_str, #len, |d| {
+                    Ok(#construct)
+                })
+            }
+        }
+    })
+}
@@ -0,0 +1,163 @@
+extern crate dbus_serialize;
+#[macro_use]
+extern crate dbus_serialize_derive;
+extern crate rustc_serialize;
+
+use std::collections::HashMap;
+
+use dbus_serialize::decoder::{DBusDecoder,DecodeErrorKind};
+use dbus_serialize::encoder::{DBusEncoder,EncoderErrorKind};
+use dbus_serialize::signature::DBusType;
+use dbus_serialize::types::{Value,BasicValue,Path,Signature,Struct,Variant};
+
+#[derive(DBusStruct,Debug,PartialEq)]
+struct Properties {
+    #[dbus(rename = "Name")]
+    name: String,
+    #[dbus(object_path)]
+    object: String,
+    #[dbus(signature)]
+    sig: String,
+    #[dbus(variant)]
+    value: u32,
+    #[dbus(skip)]
+    cached: u64,
+    counts: HashMap<String,Vec<u8>>,
+}
+
+#[derive(DBusStruct,Debug,PartialEq)]
+struct Pair(i32, #[dbus(variant)] String);
+
+#[derive(DBusStruct,Debug,PartialEq)]
+struct Wrapper<T> {
+    inner: T,
+}
+
+#[derive(DBusStruct,Debug,PartialEq)]
+struct Boxed<T> {
+    #[dbus(variant)]
+    inner: T,
+}
+
+#[test]
+fn test_signature () {
+    assert_eq!(Properties::signature().to_string(), "(sogva{say})");
+    assert_eq!(Pair::signature().to_string(), "(iv)");
+    assert_eq!(<Wrapper<Pair>>::signature().to_string(), "((iv))");
+    assert_eq!(<Boxed<u8>>::signature().to_string(), "(v)");
+}
+
+#[test]
+fn test_round_trip () {
+    let props = Properties {
+        name: "x".to_string(),
+        object: "/a/b".to_string(),
+        sig: "as".to_string(),
+        value: 7,
+        cached: 99,
+        counts: HashMap::new(),
+    };
+    let v = DBusEncoder::encode_typed(&props).unwrap();
+    assert_eq!(v.get_signature(), "(sogva{say})");
+    assert_eq!(v.validate(), Ok(()));
+    let objs = match v {
+        Value::Struct(ref x) => &x.objects,
+        _ => panic!("Expected a struct")
+    };
+    assert_eq!(objs[1], Value::BasicValue(BasicValue::ObjectPath(Path("/a/b".to_string()))));
+    assert_eq!(objs[2], Value::BasicValue(BasicValue::Signature(Signature("as".to_string()))));
+    assert_eq!(objs[3], Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(7)), "u")));
+
+    let decoded : Properties = DBusDecoder::decode_typed(v).unwrap();
+    assert_eq!(decoded, Properties { cached: 0, ..props });
+}
+
+#[test]
+fn test_tuple_struct () {
+    let v = DBusEncoder::encode_typed(&Wrapper { inner: Pair(3, "s".to_string()) }).unwrap();
+    let expected = Value::Struct(Struct {
+        objects: vec![Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::Int32(3)),
+                Value::Variant(Variant::new(Value::BasicValue(BasicValue::String("s".to_string())), "s")),
+            ],
+            signature: Signature("(iv)".to_string())
+        })],
+        signature: Signature("((iv))".to_string())
+    });
+    assert_eq!(v, expected);
+    let decoded : Wrapper<Pair> = DBusDecoder::decode_typed(v).unwrap();
+    assert_eq!(decoded.inner, Pair(3, "s".to_string()));
+}
+
+#[test]
+fn test_untyped () {
+    let props = Properties {
+        name: "x".to_string(),
+        object: "/a/b".to_string(),
+        sig: "as".to_string(),
+        value: 7,
+        cached: 99,
+        counts: vec![("k".to_string(), vec![1u8])].into_iter().collect(),
+    };
+    let v = DBusEncoder::encode(&vec![props]).unwrap();
+    assert_eq!(v.get_signature(), "a(sogva{say})");
+    assert_eq!(v.validate(), Ok(()));
+    let decoded : Vec<Properties> = DBusDecoder::decode(v).unwrap();
+    assert_eq!(decoded[0].object, "/a/b");
+    assert_eq!(decoded[0].sig, "as");
+    assert_eq!(decoded[0].value, 7);
+
+    let v = DBusEncoder::encode(&Boxed { inner: Pair(1, "s".to_string()) }).unwrap();
+    assert_eq!(v.get_signature(), "(v)");
+    let decoded : Boxed<Pair> = DBusDecoder::decode(v).unwrap();
+    assert_eq!(decoded.inner, Pair(1, "s".to_string()));
+}
+
+#[test]
+fn test_invalid_path () {
+    let mut props = Properties {
+        name: "x".to_string(),
+        object: "a/b".to_string(),
+        sig: "as".to_string(),
+        value: 7,
+        cached: 99,
+        counts: HashMap::new(),
+    };
+    let err = DBusEncoder::encode_typed(&props).err().unwrap();
+    assert_eq!(err.kind, EncoderErrorKind::InvalidObjectPath);
+    assert_eq!(err.path, ".object");
+    assert_eq!(DBusEncoder::encode(&props).err().unwrap().kind, EncoderErrorKind::InvalidObjectPath);
+
+    props.object = "/a/b".to_string();
+    props.sig = "a".to_string();
+    assert_eq!(DBusEncoder::encode(&props).err().unwrap().kind, EncoderErrorKind::InvalidSignature);
+
+    let v = Value::Struct(Struct {
+        objects: vec![
+            Value::BasicValue(BasicValue::String("x".to_string())),
+            Value::BasicValue(BasicValue::ObjectPath(Path("a/b".to_string()))),
+            Value::BasicValue(BasicValue::Signature(Signature("as".to_string()))),
+            Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(7)), "u")),
+            Value::BasicValue(BasicValue::Uint32(7)),
+        ],
+        signature: Signature("(sogvu)".to_string())
+    });
+    let err = DBusDecoder::decode::<Properties>(v).err().unwrap();
+    assert_eq!(err.kind, DecodeErrorKind::Custom);
+    assert_eq!(err.path, ".object");
+}
+
+#[test]
+fn test_rename () {
+    let props = Properties {
+        name: "x".to_string(),
+        object: "/a".to_string(),
+        sig: "as".to_string(),
+        value: 7,
+        cached: 99,
+        counts: HashMap::new(),
+    };
+    let json = rustc_serialize::json::encode(&props).unwrap();
+    assert!(json.starts_with("{\"Name\":\"x\","));
+}
@@ -1,147 +1,339 @@
 //! Implements the rustc_serialize::Decoder trait
+#![allow(deprecated, clippy::legacy_numeric_constants, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
 use std;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::rc::Rc;
 
 use rustc_serialize::{Decoder,Decodable};
 
-use types::{BasicValue,Value};
+use encoder::{EnumEncoding,OptionEncoding,VALUE_ENUM_NAME};
+use signature::{DBusType,SignatureType};
+use typed::{TYPED_ENUM_NAME,TYPE_HINT,SlotGuard};
+use types::{BasicValue,Value,Path,Signature,Array,Dictionary,DictEntry,Struct,Maybe,Variant};
 
+/// What went wrong while decoding
 #[derive(Debug,PartialEq)]
-pub enum DecodeError {
+pub enum DecodeErrorKind {
+    /// The Value does not have the type being decoded
     BadSignature,
     NotSupported,
-    IntTooNarrow
+    /// An integer does not fit in the Rust type being decoded
+    IntTooNarrow,
+    /// A tuple was decoded from a struct with a different number of fields
+    WrongArity { expected: usize, found: usize },
+    /// Raised by a Decodable implementation through Decoder::error
+    Custom,
+}
+
+/// Returned when a Value cannot be decoded.  path is the location of the failing element within
+/// the Value, made up of ".name" for a struct field, ".0" for a tuple or enum field, "[0]" for an
+/// array element and "{key}" for a dictionary entry, e.g. `{"Metadata"}.title`.
+#[derive(Debug,PartialEq)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    pub path: String,
+    /// The Rust type being decoded, such as "u32" or the name of a struct
+    pub expected: String,
+    /// The signature of the Value found
+    pub actual: String,
+    pub message: Option<String>,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.path.is_empty() {
+            try!(write!(f, "at {}: ", self.path));
+        }
+        match &self.kind {
+            &DecodeErrorKind::BadSignature =>
+                try!(write!(f, "cannot decode a value of signature '{}' as {}", self.actual, self.expected)),
+            &DecodeErrorKind::NotSupported =>
+                try!(write!(f, "{} cannot be represented in D-Bus", self.expected)),
+            &DecodeErrorKind::IntTooNarrow =>
+                try!(write!(f, "integer of signature '{}' does not fit in {}", self.actual, self.expected)),
+            &DecodeErrorKind::WrongArity { expected, found } =>
+                try!(write!(f, "expected a struct of {} fields for {}, found '{}' with {}",
+                            expected, self.expected, self.actual, found)),
+            &DecodeErrorKind::Custom =>
+                return f.write_str(self.message.as_ref().map_or("custom error", |x| &x[..])),
+        }
+        match self.message {
+            Some(ref x) => write!(f, ": {}", x),
+            None => Ok(())
+        }
+    }
+}
+
+impl error::Error for DecodeError {
 }
 
 pub struct DBusDecoder {
-    value: Value
+    value: Value,
+    path: String,
+    enum_encodings: Rc<HashMap<String,EnumEncoding>>,
+    enum_encoding: EnumEncoding,
+    enum_name: String,
+    enum_args: usize,
+    struct_name: String,
+    option_encoding: Rc<OptionEncoding>,
+    unwrap_variants: bool
+}
+
+/// Returns the path component for the dictionary entry with the given key
+pub(crate) fn key_path (key: &BasicValue) -> String {
+    let key = match key {
+        &BasicValue::Byte(x) => x.to_string(),
+        &BasicValue::Boolean(x) => x.to_string(),
+        &BasicValue::Double(x) => x.to_string(),
+        &BasicValue::Int16(x) => x.to_string(),
+        &BasicValue::Uint16(x) => x.to_string(),
+        &BasicValue::Int32(x) => x.to_string(),
+        &BasicValue::Uint32(x) => x.to_string(),
+        &BasicValue::Int64(x) => x.to_string(),
+        &BasicValue::Uint64(x) => x.to_string(),
+        &BasicValue::String(ref x) => format!("{:?}", x),
+        &BasicValue::ObjectPath(ref x) => format!("{:?}", x.0),
+        &BasicValue::Signature(ref x) => format!("{:?}", x.0),
+    };
+    format!("{{{}}}", key)
 }
 
 impl DBusDecoder {
-    fn get_unsigned_int (v: &BasicValue) -> Result<u64,DecodeError> {
-        let val = match v {
-            &BasicValue::Byte(x) => x as u64,
-            &BasicValue::Uint16(x) => x as u64,
-            &BasicValue::Uint32(x) => x as u64,
-            &BasicValue::Uint64(x) => x,
-            _ => return Err(DecodeError::BadSignature)
-        };
-        Ok(val)
+    fn get_unsigned_int (v: &BasicValue) -> Option<u64> {
+        match v {
+            &BasicValue::Byte(x) => Some(x as u64),
+            &BasicValue::Uint16(x) => Some(x as u64),
+            &BasicValue::Uint32(x) => Some(x as u64),
+            &BasicValue::Uint64(x) => Some(x),
+            _ => None
+        }
     }
 
-    fn get_signed_int (v: &BasicValue) -> Result<i64,DecodeError> {
-        let val = match v {
-            &BasicValue::Int16(x) => x as i64,
-            &BasicValue::Int32(x) => x as i64,
-            &BasicValue::Int64(x) => x as i64,
-            _ => return Err(DecodeError::BadSignature)
-        };
-        Ok(val)
+    fn get_signed_int (v: &BasicValue) -> Option<i64> {
+        match v {
+            &BasicValue::Int16(x) => Some(x as i64),
+            &BasicValue::Int32(x) => Some(x as i64),
+            &BasicValue::Int64(x) => Some(x),
+            _ => None
+        }
     }
 
-    fn read_unsigned_int (v: &Value, max: usize) -> Result<u64,DecodeError> {
-        let basic_val = match v {
-            &Value::BasicValue(ref x) => x,
-            _ => return Err(DecodeError::BadSignature)
-        };
+    /// Returns an error at the current location
+    fn fail (&self, kind: DecodeErrorKind, expected: &str) -> DecodeError {
+        DecodeError {
+            kind: kind,
+            path: self.path.clone(),
+            expected: expected.to_string(),
+            actual: self.value.get_signature().to_string(),
+            message: None
+        }
+    }
 
+    fn read_unsigned_int (&mut self, max: u64, expected: &str) -> Result<u64,DecodeError> {
+        self.unwrap_variant();
+        let x = match self.value {
+            Value::BasicValue(ref x) => DBusDecoder::get_unsigned_int(x),
+            _ => None
+        };
         // Make sure the value will fit
-        let x = try!(DBusDecoder::get_unsigned_int(basic_val));
-        if x > (max as u64) {
-            return Err(DecodeError::IntTooNarrow);
+        match x {
+            Some(x) if x <= max => Ok(x),
+            Some(_) => Err(self.fail(DecodeErrorKind::IntTooNarrow, expected)),
+            None => Err(self.fail(DecodeErrorKind::BadSignature, expected))
         }
-        Ok(x)
     }
 
-    fn read_signed_int (v: &Value, max: isize, min: isize) -> Result<i64,DecodeError> {
-        let basic_val = match v {
-            &Value::BasicValue(ref x) => x,
-            _ => return Err(DecodeError::BadSignature)
+    fn read_signed_int (&mut self, max: i64, min: i64, expected: &str) -> Result<i64,DecodeError> {
+        self.unwrap_variant();
+        let x = match self.value {
+            Value::BasicValue(ref x) => DBusDecoder::get_signed_int(x),
+            _ => None
         };
-        let x = try!(DBusDecoder::get_signed_int(basic_val));
-
         // Make sure the value will fit
-        if x > (max as i64) {
-            return Err(DecodeError::IntTooNarrow);
-        }
-        if x < (min as i64) {
-            return Err(DecodeError::IntTooNarrow);
+        match x {
+            Some(x) if x <= max && x >= min => Ok(x),
+            Some(_) => Err(self.fail(DecodeErrorKind::IntTooNarrow, expected)),
+            None => Err(self.fail(DecodeErrorKind::BadSignature, expected))
         }
-        Ok(x)
     }
 
     pub fn new (v: Value) -> DBusDecoder {
         DBusDecoder{
-            value: v
+            value: v,
+            path: String::new(),
+            enum_encodings: Rc::new(HashMap::new()),
+            enum_encoding: EnumEncoding::Discriminant,
+            enum_name: String::new(),
+            enum_args: 0,
+            struct_name: String::new(),
+            option_encoding: Rc::new(OptionEncoding::Array),
+            unwrap_variants: true
+        }
+    }
+
+    /// Sets the encoding expected for Options.  This must match the encoding given to
+    /// DBusEncoder::set_option_encoding.
+    pub fn set_option_encoding(&mut self, encoding: OptionEncoding) {
+        self.option_encoding = Rc::new(encoding);
+    }
+
+    /// Sets whether a Variant is replaced by its contents when it is decoded into a type which is
+    /// not a Variant, e.g. a u32 or the values of a HashMap<String,u32> read from an a{sv}.  This
+    /// is on by default.  Value and Variant always receive Variants as they are.
+    pub fn set_unwrap_variants(&mut self, unwrap: bool) {
+        self.unwrap_variants = unwrap;
+    }
+
+    /// Replaces the value being decoded by its contents while it is a Variant, if unwrapping is on
+    fn unwrap_variant (&mut self) {
+        if !self.unwrap_variants {
+            return;
+        }
+        while let Value::Variant(_) = self.value {
+            let mut value = Value::BasicValue(BasicValue::Byte(0));
+            std::mem::swap(&mut self.value, &mut value);
+            if let Value::Variant(x) = value {
+                self.value = *x.object;
+            }
+        }
+    }
+
+    /// Decodes the value held by this decoder, using its enum and option encodings
+    pub fn decode_value<T: Decodable>(&mut self) -> Result<T,DecodeError> {
+        T::decode(self)
+    }
+
+    /// Like decode_value, but unwraps Variants as decode_typed does
+    pub fn decode_value_typed<T: Decodable + DBusType>(&mut self) -> Result<T,DecodeError> {
+        let mut value = Value::BasicValue(BasicValue::Byte(0));
+        std::mem::swap(&mut self.value, &mut value);
+        self.value = unwrap_variants(value, &self.option_encoding.resolve(&T::signature()));
+        T::decode(self)
+    }
+
+    /// Sets the encoding expected for the Rust enum type called name.  This must match the
+    /// encoding given to DBusEncoder::set_enum_encoding.
+    pub fn set_enum_encoding(&mut self, name: &str, encoding: EnumEncoding) {
+        Rc::make_mut(&mut self.enum_encodings).insert(name.to_string(), encoding);
+    }
+
+    /// Creates a decoder for a value nested inside this one.  component is appended to the path.
+    fn child (&self, v: Value, component: &str) -> DBusDecoder {
+        DBusDecoder{
+            value: v,
+            path: self.path.clone() + component,
+            enum_encodings: self.enum_encodings.clone(),
+            enum_encoding: EnumEncoding::Discriminant,
+            enum_name: String::new(),
+            enum_args: 0,
+            struct_name: String::new(),
+            option_encoding: self.option_encoding.clone(),
+            unwrap_variants: self.unwrap_variants
+        }
+    }
+
+    /// Finds the variant index given by an enum tag
+    fn read_enum_tag (&self, tag: &BasicValue, names: &[&str]) -> Result<usize,DecodeError> {
+        let idx = match (self.enum_encoding, tag) {
+            (EnumEncoding::Discriminant, _) => DBusDecoder::get_unsigned_int(tag).map(|x| x as usize),
+            (_, &BasicValue::String(ref x)) => names.iter().position(|n| n == x),
+            _ => None
+        };
+        match idx {
+            Some(x) if x < names.len() => Ok(x),
+            _ => Err(self.fail(DecodeErrorKind::BadSignature, &self.enum_name))
+        }
+    }
+
+    /// Checks that the value is a struct of len fields, then calls f.  name is the Rust type being
+    /// decoded.
+    fn read_fields<T, F>(&mut self, name: &str, len: usize, f: F) -> Result<T,DecodeError> where F: FnOnce(&mut Self) -> Result<T,DecodeError> {
+        self.unwrap_variant();
+        let found = match self.value {
+            Value::Struct(ref x) => x.objects.len(),
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, name))
+        };
+        if found != len {
+            return Err(self.fail(DecodeErrorKind::WrongArity { expected: len, found: found }, name));
         }
+        f(self)
+    }
+
+    /// Decodes field idx of the struct holding an enum variant's fields
+    fn read_enum_field<T, F>(&mut self, idx: usize, component: &str, f: F) -> Result<T,DecodeError> where F: FnOnce(&mut Self) -> Result<T,DecodeError> {
+        let val = match self.value {
+            Value::Struct(ref mut x) if idx < x.objects.len() => {
+                x.objects.push(Value::BasicValue(BasicValue::Byte(0)));
+                x.objects.swap_remove(idx)
+            },
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, &self.enum_name))
+        };
+        self.enum_args += 1;
+        let mut subdecoder = self.child(val, component);
+        f(&mut subdecoder)
     }
 
     pub fn decode<T: Decodable>(v: Value) -> Result<T,DecodeError> {
         let mut decoder = DBusDecoder::new(v);
         T::decode(&mut decoder)
     }
+
+    /// Like decode, but uses the static type of T to unwrap any Variants in positions where
+    /// T::signature() has a 'v'.  This is the counterpart of DBusEncoder::encode_typed.
+    pub fn decode_typed<T: Decodable + DBusType>(v: Value) -> Result<T,DecodeError> {
+        DBusDecoder::new(v).decode_value_typed()
+    }
 }
 
 impl Decoder for DBusDecoder {
     type Error = DecodeError;
 
     fn read_usize(&mut self) -> Result<usize, Self::Error> {
-        let basic_val = match &self.value {
-            &Value::BasicValue(ref x) => x,
-            _ => return Err(DecodeError::BadSignature)
-        };
-        let x = try!(DBusDecoder::get_unsigned_int(basic_val));
-        Ok(x as usize)
+        Ok(try!(self.read_unsigned_int(std::usize::MAX as u64, "usize")) as usize)
     }
     fn read_u64(&mut self) -> Result<u64, Self::Error> {
-        let val = try!(self.read_usize());
-        Ok(val as u64)
+        self.read_unsigned_int(std::u64::MAX, "u64")
     }
     fn read_u32(&mut self) -> Result<u32, Self::Error> {
-        Ok(try!(DBusDecoder::read_unsigned_int(&self.value, std::u32::MAX as usize)) as u32)
+        Ok(try!(self.read_unsigned_int(std::u32::MAX as u64, "u32")) as u32)
     }
     fn read_u16(&mut self) -> Result<u16, Self::Error> {
-        Ok(try!(DBusDecoder::read_unsigned_int(&self.value, std::u16::MAX as usize)) as u16)
+        Ok(try!(self.read_unsigned_int(std::u16::MAX as u64, "u16")) as u16)
     }
     fn read_u8(&mut self) -> Result<u8, Self::Error> {
-        Ok(try!(DBusDecoder::read_unsigned_int(&self.value, std::u8::MAX as usize)) as u8)
+        Ok(try!(self.read_unsigned_int(std::u8::MAX as u64, "u8")) as u8)
     }
 
     fn read_isize(&mut self) -> Result<isize, Self::Error> {
-        let basic_val = match &self.value {
-            &Value::BasicValue(ref x) => x,
-            _ => return Err(DecodeError::BadSignature)
-        };
-        let x = try!(DBusDecoder::get_signed_int(basic_val));
-        Ok(x as isize)
+        Ok(try!(self.read_signed_int(std::isize::MAX as i64, std::isize::MIN as i64, "isize")) as isize)
     }
     fn read_i64(&mut self) -> Result<i64, Self::Error> {
-        let val = try!(self.read_isize());
-        Ok(val as i64)
+        self.read_signed_int(std::i64::MAX, std::i64::MIN, "i64")
     }
     fn read_i32(&mut self) -> Result<i32, Self::Error> {
-        Ok(try!(DBusDecoder::read_signed_int(&self.value, std::i32::MAX as isize, std::i32::MIN as isize)) as i32)
+        Ok(try!(self.read_signed_int(std::i32::MAX as i64, std::i32::MIN as i64, "i32")) as i32)
     }
     fn read_i16(&mut self) -> Result<i16, Self::Error> {
-        Ok(try!(DBusDecoder::read_signed_int(&self.value, std::i16::MAX as isize, std::i16::MIN as isize)) as i16)
+        Ok(try!(self.read_signed_int(std::i16::MAX as i64, std::i16::MIN as i64, "i16")) as i16)
     }
     fn read_i8(&mut self) -> Result<i8, Self::Error> {
-        Ok(try!(DBusDecoder::read_signed_int(&self.value, std::i8::MAX as isize, std::i8::MIN as isize)) as i8)
+        Ok(try!(self.read_signed_int(std::i8::MAX as i64, std::i8::MIN as i64, "i8")) as i8)
     }
     fn read_bool(&mut self) -> Result<bool, Self::Error> {
-        let basic_val = match &self.value {
-            &Value::BasicValue(ref x) => x,
-            _ => return Err(DecodeError::BadSignature)
-        };
-        let x = match basic_val {
-            &BasicValue::Boolean(x) => x,
-            _ => return Err(DecodeError::BadSignature)
-        };
-        Ok(x)
+        self.unwrap_variant();
+        match self.value {
+            Value::BasicValue(BasicValue::Boolean(x)) => Ok(x),
+            _ => Err(self.fail(DecodeErrorKind::BadSignature, "bool"))
+        }
     }
     fn read_f64(&mut self) -> Result<f64, Self::Error> {
-        match &self.value {
-            &Value::BasicValue(BasicValue::Double(x)) => Ok(x),
-            _ => return Err(DecodeError::BadSignature)
+        self.unwrap_variant();
+        match self.value {
+            Value::BasicValue(BasicValue::Double(x)) => Ok(x),
+            _ => Err(self.fail(DecodeErrorKind::BadSignature, "f64"))
         }
     }
     fn read_char(&mut self) -> Result<char, Self::Error> {
@@ -149,134 +341,315 @@ impl Decoder for DBusDecoder {
         Ok(val as char)
     }
     fn read_str(&mut self) -> Result<String, Self::Error> {
+        self.unwrap_variant();
         let basic_val = match &self.value {
             &Value::BasicValue(ref x) => x,
-            _ => return Err(DecodeError::BadSignature)
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "String"))
         };
         let x = match basic_val {
             &BasicValue::String(ref x) => x.to_string(),
             &BasicValue::ObjectPath(ref x) => x.0.to_string(),
             &BasicValue::Signature(ref x) => x.0.to_string(),
-            _ => return Err(DecodeError::BadSignature)
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "String"))
         };
         Ok(x)
     }
 
     fn read_seq<T, F>(&mut self, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self, usize) -> Result<T, Self::Error> {
+        self.unwrap_variant();
         let len = match self.value {
             Value::Array(ref x) => x.objects.len(),
-            _ => return Err(DecodeError::BadSignature)
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "sequence"))
         };
         f(self, len)
     }
     fn read_seq_elt<T, F>(&mut self, idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
         let val = match self.value {
-            Value::Array(ref mut x) => {
+            Value::Array(ref mut x) if idx < x.objects.len() => {
                 x.objects.push(Value::BasicValue(BasicValue::Byte(0)));
                 x.objects.swap_remove(idx)
             },
-            _ => return Err(DecodeError::BadSignature)
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "sequence"))
         };
-        let mut subdecoder = DBusDecoder::new(val);
+        let mut subdecoder = self.child(val, &format!("[{}]", idx));
         f(&mut subdecoder)
     }
 
     fn read_map<T, F>(&mut self, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self, usize) -> Result<T, Self::Error> {
+        self.unwrap_variant();
         let len = match self.value {
             Value::Dictionary(ref x) => x.entries.len(),
-            _ => return Err(DecodeError::BadSignature)
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "map"))
         };
         f(self, len)
     }
     fn read_map_elt_key<T, F>(&mut self, idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        let mut entry = match self.value {
-            Value::Dictionary(ref mut x) => {
-                &mut x.entries[idx]
-            },
-            _ => return Err(DecodeError::BadSignature)
+        // The key is left in place so that it can name the entry if its value fails to decode
+        let key = match self.value {
+            Value::Dictionary(ref x) if idx < x.entries.len() => x.entries[idx].key.clone(),
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "map"))
         };
-        let mut key = BasicValue::Byte(0);
-        std::mem::swap(&mut entry.key, &mut key);
-        let mut subdecoder = DBusDecoder::new(Value::BasicValue(key));
+        let mut subdecoder = self.child(Value::BasicValue(key.clone()), &key_path(&key));
         f(&mut subdecoder)
     }
     fn read_map_elt_val<T, F>(&mut self, idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        let mut entry = match self.value {
-            Value::Dictionary(ref mut x) => {
-                &mut x.entries[idx]
+        let (component, value) = match self.value {
+            Value::Dictionary(ref mut x) if idx < x.entries.len() => {
+                let entry = &mut x.entries[idx];
+                let mut value = Value::BasicValue(BasicValue::Byte(0));
+                std::mem::swap(&mut entry.value, &mut value);
+                (key_path(&entry.key), value)
             },
-            _ => return Err(DecodeError::BadSignature)
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "map"))
         };
-        let mut value = Value::BasicValue(BasicValue::Byte(0));
-        std::mem::swap(&mut entry.value, &mut value);
-        let mut subdecoder = DBusDecoder::new(value);
+        let mut subdecoder = self.child(value, &component);
         f(&mut subdecoder)
     }
 
-    fn read_struct<T, F>(&mut self, _s_name: &str, _len: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        match self.value {
-            Value::Struct(_) => (),
-            _ => return Err(DecodeError::BadSignature)
-        };
-        f(self)
+    fn read_struct<T, F>(&mut self, s_name: &str, len: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        self.struct_name = s_name.to_string();
+        self.read_fields(s_name, len, f)
     }
-    fn read_struct_field<T, F>(&mut self, _f_name: &str, f_idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+    fn read_struct_field<T, F>(&mut self, f_name: &str, f_idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
         let val = match self.value {
-            Value::Struct(ref mut x) => {
+            Value::Struct(ref mut x) if f_idx < x.objects.len() => {
                 x.objects.push(Value::BasicValue(BasicValue::Byte(0)));
                 x.objects.swap_remove(f_idx)
             },
-            _ => return Err(DecodeError::BadSignature)
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, &self.struct_name))
         };
-        let mut subdecoder = DBusDecoder::new(val);
+        let component = if f_name.is_empty() {
+            format!(".{}", f_idx)
+        } else {
+            format!(".{}", f_name)
+        };
+        let mut subdecoder = self.child(val, &component);
         f(&mut subdecoder)
     }
 
-    fn read_enum<T, F>(&mut self, _name: &str, _f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_enum<T, F>(&mut self, name: &str, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        if name == VALUE_ENUM_NAME {
+            let mut value = Value::BasicValue(BasicValue::Byte(0));
+            std::mem::swap(&mut self.value, &mut value);
+            let _stash = SlotGuard::set(&VALUE_STASH, value);
+            return f(self);
+        }
+        if name == TYPED_ENUM_NAME {
+            if let Some(hint) = TYPE_HINT.with(|x| x.borrow_mut().take()) {
+                let mut value = Value::BasicValue(BasicValue::Byte(0));
+                std::mem::swap(&mut self.value, &mut value);
+                // The Variant may already have been unwrapped by decode_typed
+                let value = match (hint.variant, value) {
+                    (true, Value::Variant(x)) => *x.object,
+                    (_, x) => x
+                };
+                self.value = unwrap_variants(value, &self.option_encoding.resolve(&hint.t));
+                return f(self);
+            }
+        }
+        self.enum_encoding = match self.enum_encodings.get(name) {
+            Some(x) => *x,
+            None => EnumEncoding::Discriminant
+        };
+        self.enum_name = name.to_string();
+        f(self)
     }
-    fn read_enum_variant<T, F>(&mut self, _names: &[&str], _f: F) -> Result<T, Self::Error> where F: FnMut(&mut Self, usize) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> Result<T, Self::Error> where F: FnMut(&mut Self, usize) -> Result<T, Self::Error> {
+        if self.enum_encoding != EnumEncoding::Variant {
+            self.unwrap_variant();
+            let tag = match self.value {
+                Value::BasicValue(ref x) => x.clone(),
+                Value::Struct(ref x) if x.objects.len() == 2 => match (&x.objects[0], &x.objects[1]) {
+                    (&Value::BasicValue(ref tag), &Value::Variant(_)) => tag.clone(),
+                    _ => return Err(self.fail(DecodeErrorKind::BadSignature, &self.enum_name))
+                },
+                _ => return Err(self.fail(DecodeErrorKind::BadSignature, &self.enum_name))
+            };
+            let idx = try!(self.read_enum_tag(&tag, names));
+            // Move the variant's fields out of the (tag, contents) struct
+            if let Value::Struct(_) = self.value {
+                let mut value = Value::BasicValue(BasicValue::Byte(0));
+                std::mem::swap(&mut self.value, &mut value);
+                if let Value::Struct(mut x) = value {
+                    if let Some(Value::Variant(args)) = x.objects.pop() {
+                        self.value = *args.object;
+                    }
+                }
+            }
+            return f(self, idx);
+        }
+
+        let inner = match self.value {
+            Value::Variant(ref x) => (*x.object).clone(),
+            ref x => x.clone()
+        };
+        let len = match inner {
+            Value::BasicValue(BasicValue::String(ref x)) => {
+                return match names.iter().position(|n| n == x) {
+                    Some(i) => f(self, i),
+                    None => Err(self.fail(DecodeErrorKind::BadSignature, &self.enum_name))
+                };
+            },
+            Value::Struct(ref x) => x.objects.len(),
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, &self.enum_name))
+        };
+        // Pick the first variant whose fields consume the whole struct
+        for idx in 0..names.len() {
+            let mut trial = self.child(inner.clone(), "");
+            trial.enum_encoding = self.enum_encoding;
+            trial.enum_name = self.enum_name.clone();
+            if let Ok(x) = f(&mut trial, idx) {
+                if trial.enum_args == len {
+                    return Ok(x);
+                }
+            }
+        }
+        let mut err = self.fail(DecodeErrorKind::BadSignature, &self.enum_name);
+        err.message = Some("no variant has fields matching the value".to_string());
+        Err(err)
     }
-    fn read_enum_variant_arg<T, F>(&mut self, _a_idx: usize, _f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_enum_variant_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        self.read_enum_field(a_idx, &format!(".{}", a_idx), f)
     }
-    fn read_enum_struct_variant<T, F>(&mut self, _names: &[&str], _f: F) -> Result<T, Self::Error> where F: FnMut(&mut Self, usize) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> Result<T, Self::Error> where F: FnMut(&mut Self, usize) -> Result<T, Self::Error> {
+        self.read_enum_variant(names, f)
     }
-    fn read_enum_struct_variant_field<T, F>(&mut self, _f_name: &str, _f_idx: usize, _f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_enum_struct_variant_field<T, F>(&mut self, f_name: &str, f_idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        self.read_enum_field(f_idx, &format!(".{}", f_name), f)
     }
-    fn read_tuple<T, F>(&mut self, _len: usize, _f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_tuple<T, F>(&mut self, len: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        self.read_fields("tuple", len, f)
     }
-    fn read_tuple_arg<T, F>(&mut self, _a_idx: usize, _f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_tuple_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        self.read_struct_field("", a_idx, f)
     }
-    fn read_tuple_struct<T, F>(&mut self, _s_name: &str, _len: usize, _f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_tuple_struct<T, F>(&mut self, s_name: &str, len: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        self.read_fields(s_name, len, f)
     }
-    fn read_tuple_struct_arg<T, F>(&mut self, _a_idx: usize, _f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_tuple_struct_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T, Self::Error> where F: FnOnce(&mut Self) -> Result<T, Self::Error> {
+        self.read_struct_field("", a_idx, f)
     }
-    fn read_option<T, F>(&mut self, _f: F) -> Result<T, Self::Error> where F: FnMut(&mut Self, bool) -> Result<T, Self::Error> {
-        Err(DecodeError::NotSupported)
+    fn read_option<T, F>(&mut self, mut f: F) -> Result<T, Self::Error> where F: FnMut(&mut Self, bool) -> Result<T, Self::Error> {
+        self.unwrap_variant();
+        let present = match (&*self.option_encoding, &self.value) {
+            (&OptionEncoding::Array, &Value::Array(ref x)) if x.objects.len() <= 1 => !x.objects.is_empty(),
+            (&OptionEncoding::Maybe, &Value::Maybe(ref x)) => x.object.is_some(),
+            // Omitted values never reach the decoder
+            (&OptionEncoding::Omit, _) => true,
+            (&OptionEncoding::Sentinel(ref sentinel), v) => v != sentinel,
+            _ => return Err(self.fail(DecodeErrorKind::BadSignature, "Option"))
+        };
+        if present {
+            // Take the contents out of the array or maybe
+            let mut value = Value::BasicValue(BasicValue::Byte(0));
+            std::mem::swap(&mut self.value, &mut value);
+            self.value = match (&*self.option_encoding, value) {
+                (&OptionEncoding::Array, Value::Array(mut x)) => x.objects.pop().unwrap(),
+                (&OptionEncoding::Maybe, Value::Maybe(x)) => *x.object.unwrap(),
+                (_, v) => v
+            };
+        }
+        f(self, present)
     }
     fn read_nil(&mut self) -> Result<(), Self::Error> {
-        Err(DecodeError::NotSupported)
+        Err(self.fail(DecodeErrorKind::NotSupported, "()"))
     }
     fn read_f32(&mut self) -> Result<f32, Self::Error> {
-        Err(DecodeError::NotSupported)
+        Err(self.fail(DecodeErrorKind::NotSupported, "f32"))
+    }
+    fn error(&mut self, err: &str) -> Self::Error {
+        let mut e = self.fail(DecodeErrorKind::Custom, "");
+        e.message = Some(err.to_string());
+        e
+    }
+}
+
+/// Replaces each Variant in v that is in a 'v' position of t by its contents
+fn unwrap_variants (v: Value, t: &SignatureType) -> Value {
+    let sig = v.get_signature().to_string();
+    match (v, t) {
+        (Value::Variant(x), &SignatureType::Variant) => *x.object,
+        (Value::Array(x), &SignatureType::Array(ref elem)) => {
+            let objs = x.objects.into_iter().map(|o| unwrap_variants(o, elem)).collect();
+            Value::Array(Array::new_with_sig(objs, sig))
+        },
+        (Value::Dictionary(x), &SignatureType::Dict(_, ref value)) => {
+            let entries = x.entries.into_iter().map(|e| DictEntry {
+                key: e.key,
+                value: unwrap_variants(e.value, value)
+            }).collect();
+            Value::Dictionary(Dictionary::new_with_sig(entries, sig))
+        },
+        (Value::Struct(x), &SignatureType::Struct(ref fields)) => {
+            let objs = x.objects.into_iter().zip(fields.iter()).map(|(o, f)| unwrap_variants(o, f)).collect();
+            Value::Struct(Struct {
+                objects: objs,
+                signature: Signature(sig)
+            })
+        },
+        (Value::Maybe(x), &SignatureType::Maybe(ref inner)) => {
+            let object = x.object.map(|o| unwrap_variants(*o, inner));
+            Value::Maybe(Maybe {
+                object: object.map(Box::new),
+                signature: Signature(sig)
+            })
+        },
+        (v, _) => v
     }
-    fn error(&mut self, _err: &str) -> Self::Error {
-        DecodeError::NotSupported
+}
+
+thread_local!(static VALUE_STASH: RefCell<Option<Value>> = const { RefCell::new(None) });
+
+/// Only DBusDecoder can decode a Value, which it hands over as it is
+impl Decodable for Value {
+    fn decode<S: Decoder>(s: &mut S) -> Result<Self, S::Error> {
+        s.read_enum(VALUE_ENUM_NAME, |s| {
+            match VALUE_STASH.with(|x| x.borrow_mut().take()) {
+                Some(x) => Ok(x),
+                None => Err(s.error("a Value can only be decoded by DBusDecoder"))
+            }
+        })
+    }
+}
+
+/// A value which is not a Variant, such as one unwrapped by decode_typed, is wrapped in one
+impl Decodable for Variant {
+    fn decode<S: Decoder>(s: &mut S) -> Result<Self, S::Error> {
+        match try!(Value::decode(s)) {
+            Value::Variant(x) => Ok(x),
+            x => {
+                let sig = x.get_signature().to_string();
+                Ok(Variant::new(x, &sig))
+            }
+        }
+    }
+}
+
+impl Decodable for Path {
+    fn decode<S: Decoder>(s: &mut S) -> Result<Self, S::Error> {
+        let x = Path(try!(s.read_str()));
+        if x.validate().is_err() {
+            return Err(s.error(&format!("'{}' is not a valid object path", x.0)));
+        }
+        Ok(x)
+    }
+}
+
+impl Decodable for Signature {
+    fn decode<S: Decoder>(s: &mut S) -> Result<Self, S::Error> {
+        let x = Signature(try!(s.read_str()));
+        if x.validate().is_err() {
+            return Err(s.error(&format!("'{}' is not a valid signature", x.0)));
+        }
+        Ok(x)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use rustc_serialize::{Decoder,Decodable};
-    use types::{BasicValue,Value,Path,Struct,Signature,Array,Dictionary,DictEntry};
+    use rustc_serialize::{Decoder,Decodable,Encoder,Encodable};
+    use encoder::{DBusEncoder,EncoderErrorKind,EnumEncoding,OptionEncoding};
+    use types::{BasicValue,Value,Path,Struct,Signature,Array,Dictionary,DictEntry,Variant};
     use decoder::*;
     use std::collections::HashMap;
 
@@ -300,7 +673,7 @@ mod test {
 
         let x = Value::BasicValue(BasicValue::Uint32(1024));
         let err = DBusDecoder::decode::<u8>(x).err().unwrap();
-        assert_eq!(err, DecodeError::IntTooNarrow);
+        assert_eq!(err.kind, DecodeErrorKind::IntTooNarrow);
     }
 
     #[test]
@@ -365,6 +738,32 @@ mod test {
             bar: 10,
             baz: "baz".to_string()
         });
+
+        let v = DBusEncoder::encode(&(1u8, 10u32, "baz".to_string(), 4u32)).unwrap();
+        let err = DBusDecoder::decode::<TestStruct>(v).err().unwrap();
+        assert_eq!(err.kind, DecodeErrorKind::WrongArity { expected: 3, found: 4 });
+        assert_eq!(err.expected, "TestStruct");
+    }
+
+    #[test]
+    fn test_decode_typed () {
+        let v = Value::Array(Array::new_with_sig(vec![
+            Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(5)), "u")),
+            Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(6)), "u")),
+        ], "av".to_string()));
+        let x : Vec<u32> = DBusDecoder::decode_typed(v.clone()).unwrap();
+        assert_eq!(x, vec![5, 6]);
+        // Without transparent unwrapping, variants are only unwrapped where the static type has a 'v'
+        let mut decoder = DBusDecoder::new(v);
+        decoder.set_unwrap_variants(false);
+        assert_eq!(decoder.decode_value_typed::<Vec<u32>>().err().unwrap().kind, DecodeErrorKind::BadSignature);
+        let v = Value::Array(Array::new(vec![Value::BasicValue(BasicValue::Uint32(5))]));
+        let x : Vec<u32> = DBusDecoder::decode_typed(v).unwrap();
+        assert_eq!(x, vec![5]);
+
+        let v = Value::BasicValue(BasicValue::ObjectPath(Path("/a".to_string())));
+        let x : Path = DBusDecoder::decode_typed(v).unwrap();
+        assert_eq!(x, Path("/a".to_string()));
     }
 
     #[test]
@@ -391,5 +790,266 @@ mod test {
         exp_hm.insert(9,  "foo".to_string());
         assert_eq!(hm, exp_hm);
     }
-}
 
+    #[derive(Debug,PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Rect { w: u32, h: String },
+    }
+
+    impl Decodable for Shape {
+        fn decode<S: Decoder>(s: &mut S) -> Result<Self, S::Error> {
+            s.read_enum("Shape", |s| {
+                s.read_enum_variant(&["Empty", "Circle", "Rect"], |s, idx| {
+                    match idx {
+                        0 => Ok(Shape::Empty),
+                        1 => Ok(Shape::Circle(try!(s.read_enum_variant_arg(0, |s| s.read_u32())))),
+                        _ => Ok(Shape::Rect {
+                            w: try!(s.read_enum_struct_variant_field("w", 0, |s| s.read_u32())),
+                            h: try!(s.read_enum_struct_variant_field("h", 1, |s| s.read_str())),
+                        })
+                    }
+                })
+            })
+        }
+    }
+
+    impl Encodable for Shape {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum("Shape", |s| {
+                match self {
+                    &Shape::Empty => s.emit_enum_variant("Empty", 0, 0, |_| Ok(())),
+                    &Shape::Circle(r) => s.emit_enum_variant("Circle", 1, 1, |s| {
+                        s.emit_enum_variant_arg(0, |s| r.encode(s))
+                    }),
+                    &Shape::Rect { w, ref h } => s.emit_enum_struct_variant("Rect", 2, 2, |s| {
+                        try!(s.emit_enum_struct_variant_field("w", 0, |s| w.encode(s)));
+                        s.emit_enum_struct_variant_field("h", 1, |s| h.encode(s))
+                    })
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_enum () {
+        let shapes = vec![Shape::Empty, Shape::Circle(3), Shape::Rect { w: 2, h: "tall".to_string() }];
+        for &encoding in [EnumEncoding::Discriminant, EnumEncoding::Name, EnumEncoding::Variant].iter() {
+            let mut encoder = DBusEncoder::new();
+            encoder.set_enum_encoding("Shape", encoding);
+            for shape in shapes.iter() {
+                let v = encoder.encode_value(shape).unwrap();
+                let mut decoder = DBusDecoder::new(v);
+                decoder.set_enum_encoding("Shape", encoding);
+                let x : Shape = Decodable::decode(&mut decoder).unwrap();
+                assert_eq!(&x, shape);
+            }
+        }
+
+        // Only EnumEncoding::Variant gives every variant the same signature
+        let mut encoder = DBusEncoder::new();
+        encoder.set_enum_encoding("Shape", EnumEncoding::Variant);
+        let v = encoder.encode_value(&shapes).unwrap();
+        let mut decoder = DBusDecoder::new(v);
+        decoder.set_enum_encoding("Shape", EnumEncoding::Variant);
+        let x : Vec<Shape> = Decodable::decode(&mut decoder).unwrap();
+        assert_eq!(x, shapes);
+        assert_eq!(DBusEncoder::encode(&shapes).err().unwrap().kind, EncoderErrorKind::TypeMismatch);
+
+        let v = Value::BasicValue(BasicValue::Uint32(7));
+        assert_eq!(DBusDecoder::decode::<Shape>(v).err().unwrap().kind, DecodeErrorKind::BadSignature);
+    }
+
+    #[test]
+    fn test_option () {
+        let encodings = [
+            OptionEncoding::Array,
+            OptionEncoding::Maybe,
+            OptionEncoding::Sentinel(Value::BasicValue(BasicValue::String("".to_string()))),
+        ];
+        let options = vec![Some("x".to_string()), None];
+        for encoding in encodings.iter() {
+            let mut encoder = DBusEncoder::new();
+            encoder.set_option_encoding(encoding.clone());
+            let v = encoder.encode_value_typed(&options).unwrap();
+            let mut decoder = DBusDecoder::new(v);
+            decoder.set_option_encoding(encoding.clone());
+            let x : Vec<Option<String>> = decoder.decode_value_typed().unwrap();
+            assert_eq!(x, options);
+        }
+
+        let mut props = HashMap::new();
+        props.insert("a".to_string(), Some(1u32));
+        props.insert("b".to_string(), None);
+        let mut encoder = DBusEncoder::new();
+        encoder.set_option_encoding(OptionEncoding::Omit);
+        let v = encoder.encode_value_typed(&props).unwrap();
+        let mut decoder = DBusDecoder::new(v);
+        decoder.set_option_encoding(OptionEncoding::Omit);
+        let x : HashMap<String,Option<u32>> = decoder.decode_value().unwrap();
+        assert_eq!(x.len(), 1);
+        assert_eq!(x["a"], Some(1));
+
+        let v = Value::Array(Array::new(vec![Value::BasicValue(BasicValue::Uint32(1)); 2]));
+        assert_eq!(DBusDecoder::decode::<Option<u32>>(v).err().unwrap().kind, DecodeErrorKind::BadSignature);
+    }
+
+    #[derive(Debug,PartialEq)]
+    struct Point(i32, i32);
+
+    impl Decodable for Point {
+        fn decode<S: Decoder>(s: &mut S) -> Result<Self, S::Error> {
+            s.read_tuple_struct("Point", 2, |s| {
+                let x = try!(s.read_tuple_struct_arg(0, |s| s.read_i32()));
+                let y = try!(s.read_tuple_struct_arg(1, |s| s.read_i32()));
+                Ok(Point(x, y))
+            })
+        }
+    }
+
+    #[test]
+    fn test_tuple () {
+        let v = DBusEncoder::encode(&(7u32, "seven".to_string())).unwrap();
+        let x : (u32, String) = DBusDecoder::decode(v.clone()).unwrap();
+        assert_eq!(x, (7, "seven".to_string()));
+        assert_eq!(DBusDecoder::decode::<(u32, String, bool)>(v).err().unwrap().kind,
+                   DecodeErrorKind::WrongArity { expected: 3, found: 2 });
+
+        let v = Value::Struct(Struct {
+            objects: vec![Value::BasicValue(BasicValue::Int32(1)), Value::BasicValue(BasicValue::Int32(-2))],
+            signature: Signature("(ii)".to_string())
+        });
+        assert_eq!(DBusDecoder::decode::<Point>(v.clone()).unwrap(), Point(1, -2));
+        assert_eq!(DBusDecoder::decode::<(i32,)>(v).err().unwrap().kind, DecodeErrorKind::WrongArity { expected: 1, found: 2 });
+        let v = Value::BasicValue(BasicValue::Int32(1));
+        let err = DBusDecoder::decode::<Point>(v).err().unwrap();
+        assert_eq!((err.kind, err.expected), (DecodeErrorKind::BadSignature, "Point".to_string()));
+    }
+
+    #[test]
+    fn test_value () {
+        let payload = Value::Struct(Struct {
+            objects: vec![Value::from(1u8), Value::from("x")],
+            signature: Signature("(ys)".to_string())
+        });
+        let dict = Value::Dictionary(Dictionary::new(vec![
+            DictEntry {
+                key: BasicValue::String("a".to_string()),
+                value: Value::Variant(Variant::new(payload.clone(), "(ys)"))
+            },
+            DictEntry {
+                key: BasicValue::String("b".to_string()),
+                value: Value::Variant(Variant::new(Value::from(5u32), "u"))
+            },
+        ]));
+        let x : HashMap<String,Value> = DBusDecoder::decode(dict.clone()).unwrap();
+        assert_eq!(x["a"], Value::Variant(Variant::new(payload.clone(), "(ys)")));
+        let x : HashMap<String,Variant> = DBusDecoder::decode_typed(dict).unwrap();
+        assert_eq!(x["b"], Variant::new(Value::from(5u32), "u"));
+
+        let x : (u8, String) = DBusDecoder::decode(payload).unwrap();
+        assert_eq!(x, (1, "x".to_string()));
+
+        assert!(::rustc_serialize::json::decode::<Value>("1").is_err());
+    }
+
+    #[test]
+    fn test_unwrap_variants () {
+        let inner = Value::Variant(Variant::new(Value::from(7u32), "u"));
+        let props = Value::Dictionary(Dictionary::new(vec![
+            DictEntry {
+                key: BasicValue::String("a".to_string()),
+                value: Value::Variant(Variant::new(inner.clone(), "v"))
+            },
+        ]));
+        let x : HashMap<String,u32> = DBusDecoder::decode(props.clone()).unwrap();
+        assert_eq!(x["a"], 7);
+        let x : HashMap<String,Value> = DBusDecoder::decode(props.clone()).unwrap();
+        assert_eq!(x["a"], Value::Variant(Variant::new(inner, "v")));
+
+        let mut decoder = DBusDecoder::new(props);
+        decoder.set_unwrap_variants(false);
+        assert_eq!(decoder.decode_value::<HashMap<String,u32>>().err().unwrap().kind, DecodeErrorKind::BadSignature);
+
+        let v = Value::Variant(Variant::new(Value::Struct(Struct {
+            objects: vec![Value::from(1u8), Value::Variant(Variant::new(Value::from("s"), "s"))],
+            signature: Signature("(yv)".to_string())
+        }), "(yv)"));
+        let x : (u8, String) = DBusDecoder::decode(v).unwrap();
+        assert_eq!(x, (1, "s".to_string()));
+    }
+
+    #[test]
+    fn test_error_path () {
+        let track = Value::Struct(Struct {
+            objects: vec![
+                Value::from("Song"),
+                Value::Array(Array::new(vec![
+                    Value::Variant(Variant::new(Value::from(1u32), "u")),
+                    Value::Variant(Variant::new(Value::from("x"), "s")),
+                ])),
+            ],
+            signature: Signature("(sav)".to_string())
+        });
+        let props = Value::Dictionary(Dictionary::new(vec![
+            DictEntry {
+                key: BasicValue::String("Metadata".to_string()),
+                value: Value::Variant(Variant::new(track, "(sav)"))
+            },
+        ]));
+        let err = DBusDecoder::decode::<HashMap<String,(String,Vec<u32>)>>(props).err().unwrap();
+        assert_eq!(err, DecodeError {
+            kind: DecodeErrorKind::BadSignature,
+            path: "{\"Metadata\"}.1[1]".to_string(),
+            expected: "u32".to_string(),
+            actual: "s".to_string(),
+            message: None
+        });
+
+        let x = DBusEncoder::encode(&(1u8, 1u64 << 40, "baz".to_string())).unwrap();
+        let err = DBusDecoder::decode::<TestStruct>(x).err().unwrap();
+        assert_eq!((err.kind, err.path), (DecodeErrorKind::IntTooNarrow, ".bar".to_string()));
+
+        let mut decoder = DBusDecoder::new(Value::from(1u8));
+        let err = decoder.error("custom");
+        assert_eq!(err.kind, DecodeErrorKind::Custom);
+        assert_eq!(err.message, Some("custom".to_string()));
+        assert_eq!(err.actual, "y");
+    }
+
+    #[test]
+    fn test_display () {
+        let v = Value::Struct(Struct {
+            objects: vec![Value::from(1u32), Value::from("x")],
+            signature: Signature("(us)".to_string())
+        });
+        let err = DBusDecoder::decode::<(u32,u32)>(v).err().unwrap();
+        assert_eq!(err.to_string(), "at .1: cannot decode a value of signature 's' as u32");
+        let err = DBusDecoder::decode::<u8>(Value::from(300u32)).err().unwrap();
+        assert_eq!(err.to_string(), "integer of signature 'u' does not fit in u8");
+        let v = DBusEncoder::encode(&(7u32, "seven".to_string())).unwrap();
+        let err = DBusDecoder::decode::<(u32, String, bool)>(v).err().unwrap();
+        assert_eq!(err.to_string(), "expected a struct of 3 fields for tuple, found '(us)' with 2");
+        let err = DBusDecoder::decode::<Point>(Value::from(1i32)).err().unwrap();
+        assert_eq!(err.to_string(), "cannot decode a value of signature 'i' as Point");
+        let mut decoder = DBusDecoder::new(Value::from(1u8));
+        assert_eq!(decoder.error("bad value").to_string(), "bad value");
+    }
+
+    /// Starts to decode a Value, then fails before taking it
+    struct GivesUp;
+
+    impl Decodable for GivesUp {
+        fn decode<S: Decoder>(s: &mut S) -> Result<Self, S::Error> {
+            s.read_enum(::encoder::VALUE_ENUM_NAME, |s| Err(s.error("gave up")))
+        }
+    }
+
+    #[test]
+    fn test_no_stale_value () {
+        assert!(DBusDecoder::decode::<GivesUp>(Value::from(1u8)).is_err());
+        // The Value left behind must not be picked up by a later, unrelated decode
+        assert!(::rustc_serialize::json::decode::<Value>("1").is_err());
+    }
+}
@@ -0,0 +1,380 @@
+//! Implements the serde::Deserializer trait, reading from a Value.  The mapping is the same as
+//! for DBusDecoder: Struct is read as a struct or tuple, Array as a sequence and Dictionary as a
+//! map.
+#![allow(deprecated, clippy::legacy_numeric_constants, clippy::match_ref_pats, clippy::needless_borrowed_reference)]
+use std;
+use std::error;
+use std::fmt;
+
+use serde::de::{self,Deserialize,DeserializeOwned,DeserializeSeed,IntoDeserializer,Visitor};
+
+use types::{Value,BasicValue,DictEntry,Path,Signature};
+
+#[derive(Debug,PartialEq)]
+pub enum DeserializeError {
+    BadSignature,
+    NotSupported,
+    IntTooNarrow,
+    Custom(String),
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &DeserializeError::BadSignature => f.write_str("value does not have the expected type"),
+            &DeserializeError::NotSupported => f.write_str("type cannot be represented in D-Bus"),
+            &DeserializeError::IntTooNarrow => f.write_str("integer does not fit in the target type"),
+            &DeserializeError::Custom(ref x) => f.write_str(x),
+        }
+    }
+}
+
+impl error::Error for DeserializeError {
+}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(msg: T) -> DeserializeError {
+        DeserializeError::Custom(msg.to_string())
+    }
+}
+
+/// Converts a Value to any Deserialize type
+pub fn from_value<T: DeserializeOwned>(v: Value) -> Result<T,DeserializeError> {
+    T::deserialize(ValueDeserializer::new(v))
+}
+
+pub struct ValueDeserializer {
+    value: Value
+}
+
+impl ValueDeserializer {
+    pub fn new (v: Value) -> ValueDeserializer {
+        ValueDeserializer {
+            value: v
+        }
+    }
+
+    fn unsigned_int (&self, max: u64) -> Result<u64,DeserializeError> {
+        let x = match self.value {
+            Value::BasicValue(BasicValue::Byte(x)) => x as u64,
+            Value::BasicValue(BasicValue::Uint16(x)) => x as u64,
+            Value::BasicValue(BasicValue::Uint32(x)) => x as u64,
+            Value::BasicValue(BasicValue::Uint64(x)) => x,
+            _ => return Err(DeserializeError::BadSignature)
+        };
+        if x > max {
+            return Err(DeserializeError::IntTooNarrow);
+        }
+        Ok(x)
+    }
+
+    fn signed_int (&self, max: i64, min: i64) -> Result<i64,DeserializeError> {
+        let x = match self.value {
+            Value::BasicValue(BasicValue::Int16(x)) => x as i64,
+            Value::BasicValue(BasicValue::Int32(x)) => x as i64,
+            Value::BasicValue(BasicValue::Int64(x)) => x,
+            _ => return Err(DeserializeError::BadSignature)
+        };
+        if x > max || x < min {
+            return Err(DeserializeError::IntTooNarrow);
+        }
+        Ok(x)
+    }
+
+    fn string (self) -> Result<String,DeserializeError> {
+        match self.value {
+            Value::BasicValue(BasicValue::String(x)) => Ok(x),
+            Value::BasicValue(BasicValue::ObjectPath(x)) => Ok(x.0),
+            Value::BasicValue(BasicValue::Signature(x)) => Ok(x.0),
+            _ => Err(DeserializeError::BadSignature)
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = DeserializeError;
+
+    /// Reads the value according to its own type.  Variants are unwrapped, since the contained
+    /// value is the only thing a visitor can be given.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        match self.value {
+            Value::BasicValue(x) => match x {
+                BasicValue::Byte(x) => visitor.visit_u8(x),
+                BasicValue::Boolean(x) => visitor.visit_bool(x),
+                BasicValue::Double(x) => visitor.visit_f64(x),
+                BasicValue::Int16(x) => visitor.visit_i16(x),
+                BasicValue::Uint16(x) => visitor.visit_u16(x),
+                BasicValue::Int32(x) => visitor.visit_i32(x),
+                BasicValue::Uint32(x) => visitor.visit_u32(x),
+                BasicValue::Int64(x) => visitor.visit_i64(x),
+                BasicValue::Uint64(x) => visitor.visit_u64(x),
+                BasicValue::String(x) => visitor.visit_string(x),
+                BasicValue::ObjectPath(x) => visitor.visit_string(x.0),
+                BasicValue::Signature(x) => visitor.visit_string(x.0),
+            },
+            Value::Array(x) => visitor.visit_seq(SeqAccess::new(x.objects)),
+            Value::Struct(x) => visitor.visit_seq(SeqAccess::new(x.objects)),
+            Value::Dictionary(x) => visitor.visit_map(MapAccess::new(x.entries)),
+            Value::Variant(x) => ValueDeserializer::new(*x.object).deserialize_any(visitor),
+            Value::Maybe(x) => match x.object {
+                Some(x) => visitor.visit_some(ValueDeserializer::new(*x)),
+                None => visitor.visit_none()
+            },
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        match self.value {
+            Value::BasicValue(BasicValue::Boolean(x)) => visitor.visit_bool(x),
+            _ => Err(DeserializeError::BadSignature)
+        }
+    }
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_i8(try!(self.signed_int(std::i8::MAX as i64, std::i8::MIN as i64)) as i8)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_i16(try!(self.signed_int(std::i16::MAX as i64, std::i16::MIN as i64)) as i16)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_i32(try!(self.signed_int(std::i32::MAX as i64, std::i32::MIN as i64)) as i32)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_i64(try!(self.signed_int(std::i64::MAX, std::i64::MIN)))
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_u8(try!(self.unsigned_int(std::u8::MAX as u64)) as u8)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_u16(try!(self.unsigned_int(std::u16::MAX as u64)) as u16)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_u32(try!(self.unsigned_int(std::u32::MAX as u64)) as u32)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_u64(try!(self.unsigned_int(std::u64::MAX)))
+    }
+    fn deserialize_f32<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value,DeserializeError> {
+        Err(DeserializeError::NotSupported)
+    }
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        match self.value {
+            Value::BasicValue(BasicValue::Double(x)) => visitor.visit_f64(x),
+            _ => Err(DeserializeError::BadSignature)
+        }
+    }
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_char(try!(self.unsigned_int(std::u8::MAX as u64)) as u8 as char)
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_string(try!(self.string()))
+    }
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_string(try!(self.string()))
+    }
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value,DeserializeError> {
+        Err(DeserializeError::NotSupported)
+    }
+    fn deserialize_unit<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value,DeserializeError> {
+        Err(DeserializeError::NotSupported)
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, _visitor: V)
+                                                -> Result<V::Value,DeserializeError> {
+        Err(DeserializeError::NotSupported)
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+                                                   -> Result<V::Value,DeserializeError> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        match self.value {
+            Value::Array(x) => visitor.visit_seq(SeqAccess::new(x.objects)),
+            _ => Err(DeserializeError::BadSignature)
+        }
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value,DeserializeError> {
+        match self.value {
+            Value::Struct(x) => visitor.visit_seq(SeqAccess::new(x.objects)),
+            _ => Err(DeserializeError::BadSignature)
+        }
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, len: usize, visitor: V)
+                                                 -> Result<V::Value,DeserializeError> {
+        self.deserialize_tuple(len, visitor)
+    }
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        match self.value {
+            Value::Dictionary(x) => visitor.visit_map(MapAccess::new(x.entries)),
+            _ => Err(DeserializeError::BadSignature)
+        }
+    }
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str],
+                                           visitor: V) -> Result<V::Value,DeserializeError> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str],
+                                         _visitor: V) -> Result<V::Value,DeserializeError> {
+        Err(DeserializeError::NotSupported)
+    }
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        self.deserialize_str(visitor)
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value,DeserializeError> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for Value {
+    type Deserializer = ValueDeserializer;
+
+    fn into_deserializer(self) -> ValueDeserializer {
+        ValueDeserializer::new(self)
+    }
+}
+
+struct SeqAccess {
+    iter: std::vec::IntoIter<Value>
+}
+
+impl SeqAccess {
+    fn new (objects: Vec<Value>) -> SeqAccess {
+        SeqAccess {
+            iter: objects.into_iter()
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>,DeserializeError> {
+        match self.iter.next() {
+            Some(x) => Ok(Some(try!(seed.deserialize(ValueDeserializer::new(x))))),
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapAccess {
+    iter: std::vec::IntoIter<DictEntry>,
+    value: Option<Value>
+}
+
+impl MapAccess {
+    fn new (entries: Vec<DictEntry>) -> MapAccess {
+        MapAccess {
+            iter: entries.into_iter(),
+            value: None
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>,DeserializeError> {
+        match self.iter.next() {
+            Some(x) => {
+                self.value = Some(x.value);
+                Ok(Some(try!(seed.deserialize(ValueDeserializer::new(Value::BasicValue(x.key))))))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value,DeserializeError> {
+        let value = self.value.take().unwrap();
+        seed.deserialize(ValueDeserializer::new(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+impl<'de> Deserialize<'de> for Path {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Path,D::Error> {
+        String::deserialize(d).map(Path)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Signature,D::Error> {
+        String::deserialize(d).map(Signature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use types::{Value,BasicValue,Struct,Signature,DictEntry,Dictionary,Array,Path,Variant};
+    use serializer::to_value;
+    use deserializer::*;
+
+    #[derive(Serialize,Deserialize,Debug,PartialEq)]
+    struct TestStruct {
+        x: u32,
+        path: Path,
+        y: Vec<String>,
+        z: (i16, bool),
+        map: HashMap<String,u64>,
+    }
+
+    #[test]
+    fn test_int () {
+        let v = Value::BasicValue(BasicValue::Uint32(1024));
+        assert_eq!(from_value::<u64>(v.clone()), Ok(1024));
+        assert_eq!(from_value::<u8>(v.clone()), Err(DeserializeError::IntTooNarrow));
+        assert_eq!(from_value::<i32>(v), Err(DeserializeError::BadSignature));
+    }
+
+    #[test]
+    fn test_struct () {
+        let v = Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::Uint32(1)),
+                Value::BasicValue(BasicValue::ObjectPath(Path("/a".to_string()))),
+                Value::Array(Array::new(vec![Value::BasicValue(BasicValue::String("a".to_string()))])),
+                Value::Struct(Struct {
+                    objects: vec![
+                        Value::BasicValue(BasicValue::Int16(-1)),
+                        Value::BasicValue(BasicValue::Boolean(true)),
+                    ],
+                    signature: Signature("(nb)".to_string())
+                }),
+                Value::Dictionary(Dictionary::new(vec![
+                    DictEntry {
+                        key: BasicValue::String("k".to_string()),
+                        value: Value::BasicValue(BasicValue::Uint64(2))
+                    }
+                ])),
+            ],
+            signature: Signature("(uoas(nb)a{st})".to_string())
+        });
+        let s : TestStruct = from_value(v.clone()).unwrap();
+        assert_eq!(s.x, 1);
+        assert_eq!(s.path, Path("/a".to_string()));
+        assert_eq!(s.y, vec!["a".to_string()]);
+        assert_eq!(s.z, (-1, true));
+        assert_eq!(s.map.get("k"), Some(&2));
+
+        assert_eq!(to_value(&s), Ok(v));
+    }
+
+    #[test]
+    fn test_variant () {
+        let v = Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(3)), "u"));
+        assert_eq!(from_value::<u32>(v.clone()), Err(DeserializeError::BadSignature));
+        let map = Value::Dictionary(Dictionary::new(vec![DictEntry { key: BasicValue::Byte(1), value: v }]));
+        assert_eq!(from_value::<HashMap<u8,u32>>(map), Err(DeserializeError::BadSignature));
+    }
+}
@@ -1,31 +1,222 @@
 //! Implements the rustc_serialize::Encoder trait
+#![allow(deprecated, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
+use std;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
 use rustc_serialize::{Encoder,Encodable};
 
-use types::{Value,BasicValue,Struct,Signature,DictEntry,Dictionary,Array};
+use signature::{BasicType,DBusType,SignatureType};
+use decoder::key_path;
+use typed::{TYPED_ENUM_NAME,TYPE_HINT,SlotGuard,encode_as};
+use types::{Value,BasicValue,Struct,Signature,DictEntry,Dictionary,Array,Variant,Path,Maybe};
+
+/// How a Rust enum is represented in D-Bus.  Variants with fields carry their fields as a
+/// struct inside a D-Bus variant, so a variant with a single u32 field has contents of type
+/// "(u)".
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum EnumEncoding {
+    /// Fieldless variants are sent as their index ('u').  Variants with fields are sent as a
+    /// (uv) struct of the index and the contents.
+    Discriminant,
+    /// Fieldless variants are sent as their name ('s').  Variants with fields are sent as a
+    /// (sv) struct of the name and the contents.
+    Name,
+    /// Every variant is sent as a D-Bus variant: the name for fieldless variants, otherwise just
+    /// the contents.  When decoding, the first Rust variant whose fields match the contents is
+    /// chosen.
+    Variant,
+}
+
+/// How an Option is represented.  The same encoding must be used to decode the value.
+#[derive(Clone,Debug,PartialEq)]
+pub enum OptionEncoding {
+    /// An array of zero or one elements ('aT')
+    Array,
+    /// The contained value ('T').  None may only be a dictionary value, and the entry is left out
+    /// of the dictionary, as is usual for a{sv} property maps.
+    Omit,
+    /// GVariant's maybe type ('mT').  D-Bus has no maybe type, so these Values can only be
+    /// serialized with the gvariant module.
+    Maybe,
+    /// The contained value ('T'), with None sent as the given value
+    Sentinel(Value),
+}
+
+impl OptionEncoding {
+    /// Replaces the maybe types in t, which is how DBusType describes Options, with the type
+    /// that this encoding uses
+    pub fn resolve(&self, t: &SignatureType) -> SignatureType {
+        match t {
+            &SignatureType::Maybe(ref x) => {
+                let inner = self.resolve(x);
+                match self {
+                    &OptionEncoding::Array => SignatureType::Array(Box::new(inner)),
+                    &OptionEncoding::Maybe => SignatureType::Maybe(Box::new(inner)),
+                    &OptionEncoding::Omit | &OptionEncoding::Sentinel(_) => inner,
+                }
+            },
+            &SignatureType::Array(ref x) => SignatureType::Array(Box::new(self.resolve(x))),
+            &SignatureType::Dict(k, ref v) => SignatureType::Dict(k, Box::new(self.resolve(v))),
+            &SignatureType::Struct(ref fields) => {
+                SignatureType::Struct(fields.iter().map(|x| self.resolve(x)).collect())
+            },
+            &SignatureType::Basic(_) | &SignatureType::Variant => t.clone(),
+        }
+    }
+}
 
 pub struct DBusEncoder {
     val: Vec<Value>,
-    key: Option<BasicValue>
+    key: Option<BasicValue>,
+    allow_empty: bool,
+    enum_encodings: HashMap<String,EnumEncoding>,
+    enum_stack: Vec<EnumEncoding>,
+    option_encoding: OptionEncoding,
+    // Set while a dictionary value is being encoded, until it turns out not to be an Option
+    omittable: bool,
+    // Whether the Option currently being encoded may be left out
+    none_omittable: bool,
+    // Set when a None dictionary value has been left out
+    omitted: bool,
+    // Location of the value being encoded, in the form used by DecodeError::path
+    path: String
 }
 
+/// What went wrong while encoding
 #[derive(Debug,PartialEq)]
-pub enum EncoderError {
+pub enum EncoderErrorKind {
+    /// A dictionary key is not of a basic type
     BadKeyType,
     Unsupported,
     EmptyArray,
     EmptyMap,
+    /// A value does not have the signature required by its type, or by the other elements of its
+    /// array
+    TypeMismatch,
+    /// None was encoded with OptionEncoding::Maybe, but the type it stands for is not known
+    EmptyMaybe,
+    /// None was encoded with OptionEncoding::Omit somewhere other than a dictionary value
+    NoneOutsideDictionary,
+    /// A string sent as an object path is not a valid object path
+    InvalidObjectPath,
+    /// A string sent as a signature is not a valid signature
+    InvalidSignature,
+}
+
+/// Returned when a value cannot be encoded.  path is the location of the failing element, in the
+/// same form as DecodeError::path.
+#[derive(Debug,PartialEq)]
+pub struct EncoderError {
+    pub kind: EncoderErrorKind,
+    pub path: String,
+    /// The signature required at path, if known
+    pub expected: Option<String>,
+    /// The signature of the value produced at path, if any
+    pub actual: Option<String>,
+}
+
+impl EncoderError {
+    fn new (kind: EncoderErrorKind, path: &str) -> EncoderError {
+        EncoderError {
+            kind: kind,
+            path: path.to_string(),
+            expected: None,
+            actual: None
+        }
+    }
+
+    pub(crate) fn mismatch (path: &str, expected: &str, actual: &str) -> EncoderError {
+        EncoderError {
+            kind: EncoderErrorKind::TypeMismatch,
+            path: path.to_string(),
+            expected: Some(expected.to_string()),
+            actual: Some(actual.to_string())
+        }
+    }
+
+    /// Returns an error for a string which cannot be sent as the type it was given
+    fn invalid (path: &str, kind: EncoderErrorKind, actual: String) -> EncoderError {
+        EncoderError {
+            kind: kind,
+            path: path.to_string(),
+            expected: None,
+            actual: Some(actual)
+        }
+    }
+}
+
+impl fmt::Display for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.path.is_empty() {
+            try!(write!(f, "at {}: ", self.path));
+        }
+        let actual = self.actual.as_ref().map_or("?", |x| &x[..]);
+        match self.kind {
+            EncoderErrorKind::BadKeyType =>
+                write!(f, "dictionary keys must be basic types, found '{}'", actual),
+            EncoderErrorKind::Unsupported => f.write_str("type cannot be represented in D-Bus"),
+            EncoderErrorKind::EmptyArray => f.write_str("cannot determine the signature of an empty array"),
+            EncoderErrorKind::EmptyMap => f.write_str("cannot determine the signature of an empty map"),
+            EncoderErrorKind::TypeMismatch =>
+                write!(f, "expected a value of signature '{}', found '{}'",
+                       self.expected.as_ref().map_or("?", |x| &x[..]), actual),
+            EncoderErrorKind::EmptyMaybe => f.write_str("cannot determine the signature of an empty maybe"),
+            EncoderErrorKind::NoneOutsideDictionary => f.write_str("None can only be omitted as a dictionary value"),
+            EncoderErrorKind::InvalidObjectPath => write!(f, "'{}' is not a valid object path", actual),
+            EncoderErrorKind::InvalidSignature => write!(f, "'{}' is not a valid signature", actual),
+        }
+    }
+}
+
+impl error::Error for EncoderError {
 }
 
 impl DBusEncoder {
+    /// Returns an error at the current location
+    fn fail (&self, kind: EncoderErrorKind) -> EncoderError {
+        EncoderError::new(kind, &self.path)
+    }
+
+    /// Calls f with component appended to the path
+    fn at<F> (&mut self, component: &str, f: F) -> Result<(),EncoderError> where F: FnOnce(&mut Self) -> Result<(),EncoderError> {
+        let len = self.path.len();
+        self.path.push_str(component);
+        let result = f(self);
+        self.path.truncate(len);
+        result
+    }
+
+    /// Returns the type of v.  Empty containers have no signature yet when allow_empty is set,
+    /// so None is returned for them and conform fills in the signatures later.
+    fn value_type (&self, v: &Value) -> Result<Option<SignatureType>,EncoderError> {
+        match v.get_type() {
+            Ok(t) => Ok(Some(t)),
+            Err(_) if self.allow_empty => Ok(None),
+            Err(_) => {
+                let mut err = self.fail(EncoderErrorKind::TypeMismatch);
+                err.actual = Some(v.get_signature().to_string());
+                Err(err)
+            }
+        }
+    }
+
     fn handle_struct (&mut self, len: usize) -> Result<(),EncoderError> {
-        let mut objs = Vec::new();
-        let mut sig = "(".to_string();
         let offset = self.val.len() - len;
-        for v in self.val.drain(offset..) {
-            sig.push_str(v.get_signature());
-            objs.push(v);
+        let objs: Vec<Value> = self.val.drain(offset..).collect();
+        let mut fields = Vec::new();
+        for v in objs.iter() {
+            if let Some(t) = try!(self.value_type(v)) {
+                fields.push(t);
+            }
         }
-        sig.push(')');
+        let sig = if fields.len() == objs.len() {
+            SignatureType::Struct(fields).to_string()
+        } else {
+            String::new()
+        };
         self.val.push(Value::Struct(Struct {
             objects: objs,
             signature: Signature(sig),
@@ -34,32 +225,268 @@ impl DBusEncoder {
     }
 
     fn handle_array (&mut self, len: usize) -> Result<(),EncoderError> {
-        let mut objs = Vec::new();
         let offset = self.val.len() - len;
-        for v in self.val.drain(offset..) {
-            objs.push(v);
+        let objs: Vec<Value> = self.val.drain(offset..).collect();
+        // With allow_empty, conform checks the elements against the static type instead
+        if !self.allow_empty {
+            let sig = objs[0].get_signature();
+            if let Some(i) = objs.iter().position(|x| x.get_signature() != sig) {
+                let path = format!("{}[{}]", self.path, i);
+                return Err(EncoderError::mismatch(&path, sig, objs[i].get_signature()));
+            }
         }
-        self.val.push(Value::Array(Array::new(objs)));
+        let sig = try!(self.value_type(&objs[0])).map_or(String::new(), |t| {
+            SignatureType::Array(Box::new(t)).to_string()
+        });
+        self.val.push(Value::Array(Array::new_with_sig(objs, sig)));
         Ok(())
     }
 
+    #[allow(clippy::new_without_default)]
     pub fn new() -> DBusEncoder {
         DBusEncoder {
             val: Vec::new(),
-            key: None
+            key: None,
+            allow_empty: false,
+            enum_encodings: HashMap::new(),
+            enum_stack: Vec::new(),
+            option_encoding: OptionEncoding::Array,
+            omittable: false,
+            none_omittable: false,
+            omitted: false,
+            path: String::new()
         }
     }
 
+    /// Sets the encoding used for Options.  The default is OptionEncoding::Array.
+    pub fn set_option_encoding(&mut self, encoding: OptionEncoding) {
+        self.option_encoding = encoding;
+    }
+
+    /// Sets the encoding used for the Rust enum type called name.  Enums which have not been
+    /// given an encoding use EnumEncoding::Discriminant.
+    pub fn set_enum_encoding(&mut self, name: &str, encoding: EnumEncoding) {
+        self.enum_encodings.insert(name.to_string(), encoding);
+    }
+
+    /// Like encode, but uses this encoder, and so its enum and option encodings
+    pub fn encode_value<T: Encodable>(&mut self, obj: &T) -> Result<Value,EncoderError> {
+        self.val.clear();
+        try!(obj.encode(self));
+        Ok(self.val.remove(0))
+    }
+
+    /// Like encode_typed, but uses this encoder, and so its enum and option encodings
+    pub fn encode_value_typed<T: Encodable + DBusType>(&mut self, obj: &T) -> Result<Value,EncoderError> {
+        self.val.clear();
+        let allow_empty = std::mem::replace(&mut self.allow_empty, true);
+        let result = obj.encode(self);
+        self.allow_empty = allow_empty;
+        try!(result);
+        conform(self.val.remove(0), &self.option_encoding.resolve(&T::signature()), "")
+    }
+
     pub fn encode<T: Encodable>(obj: &T) -> Result<Value,EncoderError> {
         let mut encoder = DBusEncoder::new();
         try!(obj.encode(&mut encoder));
         Ok(encoder.val.remove(0))
     }
+
+    /// Like encode, but uses the static type of T to determine signatures.  This means that empty
+    /// arrays and maps can be encoded, and that the result always has the signature T::signature().
+    pub fn encode_typed<T: Encodable + DBusType>(obj: &T) -> Result<Value,EncoderError> {
+        DBusEncoder::new().encode_value_typed(obj)
+    }
 }
 
-impl<T: Encodable> From<T> for Value {
-    fn from(x: T) -> Value {
-        DBusEncoder::encode(&x).unwrap()
+/// DBusEncoder gives a Path the type 'o'.  Other encoders see a string.
+impl Encodable for Path {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        encode_as(&self.0, Path::signature(), false, s)
+    }
+}
+
+/// DBusEncoder gives a Signature the type 'g'.  Other encoders see a string.
+impl Encodable for Signature {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        encode_as(&self.0, Signature::signature(), false, s)
+    }
+}
+
+/// Rewrites the signatures in v to match t.  Empty arrays and maps are given the right signature,
+/// strings are converted to object paths or signatures where t requires it, and values are
+/// wrapped in a Variant where t is 'v'.  path is the location of v, for errors.
+fn conform (v: Value, t: &SignatureType, path: &str) -> Result<Value,EncoderError> {
+    match (v, t) {
+        (v @ Value::Variant(_), &SignatureType::Variant) => Ok(v),
+        (v, &SignatureType::Variant) => {
+            let sig = v.get_signature().to_string();
+            Ok(Value::Variant(Variant::new(v, &sig)))
+        },
+        (Value::BasicValue(x), &SignatureType::Basic(b)) => Ok(Value::BasicValue(try!(conform_basic(x, b, path)))),
+        (Value::Array(x), &SignatureType::Array(ref elem)) => {
+            let mut objs = Vec::new();
+            for (i, obj) in x.objects.into_iter().enumerate() {
+                objs.push(try!(conform(obj, elem, &format!("{}[{}]", path, i))));
+            }
+            Ok(Value::Array(Array::new_with_sig(objs, t.to_string())))
+        },
+        (Value::Dictionary(x), &SignatureType::Dict(key, ref value)) => {
+            let mut entries = Vec::new();
+            for entry in x.entries {
+                let entry_path = format!("{}{}", path, key_path(&entry.key));
+                entries.push(DictEntry {
+                    key: try!(conform_basic(entry.key, key, &entry_path)),
+                    value: try!(conform(entry.value, value, &entry_path))
+                });
+            }
+            Ok(Value::Dictionary(Dictionary::new_with_sig(entries, t.to_string())))
+        },
+        (Value::Struct(x), &SignatureType::Struct(ref fields)) => {
+            if x.objects.len() != fields.len() {
+                return Err(EncoderError::mismatch(path, &t.to_string(), &x.signature.0));
+            }
+            let mut objs = Vec::new();
+            for (i, (obj, field)) in x.objects.into_iter().zip(fields.iter()).enumerate() {
+                objs.push(try!(conform(obj, field, &format!("{}.{}", path, i))));
+            }
+            Ok(Value::Struct(Struct {
+                objects: objs,
+                signature: Signature(t.to_string())
+            }))
+        },
+        (Value::Maybe(x), &SignatureType::Maybe(ref inner)) => {
+            let object = match x.object {
+                Some(o) => Some(try!(conform(*o, inner, path))),
+                None => None
+            };
+            Ok(Value::Maybe(Maybe::new(object, &inner.to_string())))
+        },
+        (v, t) => Err(EncoderError::mismatch(path, &t.to_string(), v.get_signature()))
+    }
+}
+
+fn conform_basic (v: BasicValue, t: BasicType, path: &str) -> Result<BasicValue,EncoderError> {
+    match (v, t) {
+        (BasicValue::String(x), BasicType::ObjectPath) => {
+            let x = Path(x);
+            if x.validate().is_err() {
+                return Err(EncoderError::invalid(path, EncoderErrorKind::InvalidObjectPath, x.0));
+            }
+            Ok(BasicValue::ObjectPath(x))
+        },
+        (BasicValue::String(x), BasicType::Signature) => {
+            let x = Signature(x);
+            if x.validate().is_err() {
+                return Err(EncoderError::invalid(path, EncoderErrorKind::InvalidSignature, x.0));
+            }
+            Ok(BasicValue::Signature(x))
+        },
+        (v, t) => {
+            if v.get_signature().starts_with(t.code()) {
+                Ok(v)
+            } else {
+                Err(EncoderError::mismatch(path, &t.code().to_string(), v.get_signature()))
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Converts any Encodable type to a Value, returning an error if it cannot be represented
+    /// (e.g. an empty Vec, whose element type cannot be determined).  This replaces the blanket
+    /// From<T: Encodable> impl, which would overlap with From<Value> now that Value is Encodable.
+    pub fn try_from_encodable<T: Encodable>(x: &T) -> Result<Value,EncoderError> {
+        DBusEncoder::encode(x)
+    }
+}
+
+/// The enum name under which Value passes itself to Encoder::emit_enum.  DBusEncoder recognises
+/// it and puts Stash::Wanted in VALUE_STASH, and the Value then replaces that with a copy of
+/// itself rather than encoding its data.
+pub(crate) const VALUE_ENUM_NAME: &str = "dbus_serialize::types::Value";
+
+enum Stash {
+    Wanted,
+    Filled(Value)
+}
+
+thread_local!(static VALUE_STASH: RefCell<Option<Stash>> = const { RefCell::new(None) });
+
+/// DBusEncoder embeds the Value as it is.  Other encoders are given the plain data, with
+/// variants replaced by their contents.
+impl Encodable for Value {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_enum(VALUE_ENUM_NAME, |s| {
+            let wanted = VALUE_STASH.with(|x| {
+                let mut stash = x.borrow_mut();
+                match *stash {
+                    Some(Stash::Wanted) => {
+                        *stash = Some(Stash::Filled(self.clone()));
+                        true
+                    },
+                    _ => false
+                }
+            });
+            if wanted {
+                Ok(())
+            } else {
+                encode_plain(self, s)
+            }
+        })
+    }
+}
+
+impl Encodable for Variant {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        Value::Variant(self.clone()).encode(s)
+    }
+}
+
+fn encode_plain<S: Encoder>(v: &Value, s: &mut S) -> Result<(), S::Error> {
+    match v {
+        &Value::BasicValue(ref x) => encode_basic(x, s),
+        &Value::Array(ref x) => s.emit_seq(x.objects.len(), |s| {
+            for (i, obj) in x.objects.iter().enumerate() {
+                try!(s.emit_seq_elt(i, |s| encode_plain(obj, s)));
+            }
+            Ok(())
+        }),
+        &Value::Dictionary(ref x) => s.emit_map(x.entries.len(), |s| {
+            for (i, entry) in x.entries.iter().enumerate() {
+                try!(s.emit_map_elt_key(i, |s| encode_basic(&entry.key, s)));
+                try!(s.emit_map_elt_val(i, |s| encode_plain(&entry.value, s)));
+            }
+            Ok(())
+        }),
+        &Value::Struct(ref x) => s.emit_tuple(x.objects.len(), |s| {
+            for (i, obj) in x.objects.iter().enumerate() {
+                try!(s.emit_tuple_arg(i, |s| encode_plain(obj, s)));
+            }
+            Ok(())
+        }),
+        &Value::Variant(ref x) => encode_plain(&x.object, s),
+        &Value::Maybe(ref x) => s.emit_option(|s| match x.object {
+            Some(ref obj) => s.emit_option_some(|s| encode_plain(obj, s)),
+            None => s.emit_option_none()
+        }),
+    }
+}
+
+fn encode_basic<S: Encoder>(v: &BasicValue, s: &mut S) -> Result<(), S::Error> {
+    match v {
+        &BasicValue::Byte(x) => s.emit_u8(x),
+        &BasicValue::Boolean(x) => s.emit_bool(x),
+        &BasicValue::Double(x) => s.emit_f64(x),
+        &BasicValue::Int16(x) => s.emit_i16(x),
+        &BasicValue::Uint16(x) => s.emit_u16(x),
+        &BasicValue::Int32(x) => s.emit_i32(x),
+        &BasicValue::Uint32(x) => s.emit_u32(x),
+        &BasicValue::Int64(x) => s.emit_i64(x),
+        &BasicValue::Uint64(x) => s.emit_u64(x),
+        &BasicValue::String(ref x) => s.emit_str(x),
+        &BasicValue::ObjectPath(ref x) => s.emit_str(&x.0),
+        &BasicValue::Signature(ref x) => s.emit_str(&x.0),
     }
 }
 
@@ -67,7 +494,7 @@ impl Encoder for DBusEncoder {
     type Error = EncoderError;
 
     fn emit_nil(&mut self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+        Err(self.fail(EncoderErrorKind::Unsupported))
     }
     fn emit_usize(&mut self, v: usize) -> Result<(), Self::Error> {
         self.val.push(Value::BasicValue(BasicValue::Uint64(v as u64)));
@@ -106,7 +533,7 @@ impl Encoder for DBusEncoder {
         Ok(())
     }
     fn emit_i8(&mut self, _v: i8) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+        Err(self.fail(EncoderErrorKind::Unsupported))
     }
     fn emit_bool(&mut self, v: bool) -> Result<(), Self::Error> {
         self.val.push(Value::BasicValue(BasicValue::Boolean(v)));
@@ -130,41 +557,55 @@ impl Encoder for DBusEncoder {
     }
 
     fn emit_struct<F>(&mut self, _name: &str, len: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.omittable = false;
         try!(f(self));
         self.handle_struct(len)
     }
-    fn emit_struct_field<F>(&mut self, _f_name: &str, _f_idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        f(self)
+    fn emit_struct_field<F>(&mut self, f_name: &str, f_idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        if f_name.is_empty() {
+            self.at(&format!(".{}", f_idx), f)
+        } else {
+            self.at(&format!(".{}", f_name), f)
+        }
     }
     fn emit_tuple<F>(&mut self, len: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.omittable = false;
         try!(f(self));
         self.handle_struct(len)
     }
-    fn emit_tuple_arg<F>(&mut self, _idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        f(self)
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.at(&format!(".{}", idx), f)
     }
     fn emit_tuple_struct<F>(&mut self, _name: &str, len: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.omittable = false;
         try!(f(self));
         self.handle_struct(len)
     }
-    fn emit_tuple_struct_arg<F>(&mut self, _f_idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        f(self)
+    fn emit_tuple_struct_arg<F>(&mut self, f_idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.at(&format!(".{}", f_idx), f)
     }
 
     fn emit_seq<F>(&mut self, len: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.omittable = false;
         if len == 0 {
-            return Err(EncoderError::EmptyArray)
+            if !self.allow_empty {
+                return Err(self.fail(EncoderErrorKind::EmptyArray))
+            }
+            // The signature is filled in by conform
+            self.val.push(Value::Array(Array::new_with_sig(Vec::new(), "".to_string())));
+            return Ok(())
         }
         try!(f(self));
         self.handle_array(len)
     }
-    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        f(self)
+    fn emit_seq_elt<F>(&mut self, idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.at(&format!("[{}]", idx), f)
     }
 
     fn emit_map<F>(&mut self, len: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        if len == 0 {
-            return Err(EncoderError::EmptyMap)
+        self.omittable = false;
+        if len == 0 && !self.allow_empty {
+            return Err(self.fail(EncoderErrorKind::EmptyMap))
         }
         // Yes, i'm intentionally creating a Dictionary with an invalid signature...
         let map : Dictionary = Dictionary::new_with_sig(Vec::new(), "".to_string());
@@ -176,20 +617,53 @@ impl Encoder for DBusEncoder {
             Value::Dictionary(x) => x.entries,
             _ => panic!("Where'd my dictionary go?!")
         };
-        self.val.push(Value::Dictionary(Dictionary::new(x)));
+        // As for arrays, conform checks the entries against the static type with allow_empty
+        if !self.allow_empty && !x.is_empty() {
+            let key_sig = x[0].key.get_signature();
+            let value_sig = x[0].value.get_signature();
+            for entry in x.iter() {
+                let path = self.path.clone() + &key_path(&entry.key);
+                if entry.key.get_signature() != key_sig {
+                    return Err(EncoderError::mismatch(&path, key_sig, entry.key.get_signature()));
+                }
+                if entry.value.get_signature() != value_sig {
+                    return Err(EncoderError::mismatch(&path, value_sig, entry.value.get_signature()));
+                }
+            }
+        }
+        let sig = match x.first() {
+            Some(entry) => try!(self.value_type(&entry.value)).map_or(String::new(), |t| {
+                SignatureType::Dict(entry.key.get_basic_type(), Box::new(t)).to_string()
+            }),
+            // Every entry may have been left out.  conform fills in the signature.
+            None if self.allow_empty => String::new(),
+            None => return Err(self.fail(EncoderErrorKind::EmptyMap))
+        };
+        self.val.push(Value::Dictionary(Dictionary::new_with_sig(x, sig)));
         Ok(())
     }
     fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
         try!(f(self));
         self.key = match self.val.pop().unwrap() {
             Value::BasicValue(x) => Some(x),
-            _ => return Err(EncoderError::BadKeyType)
+            v => {
+                let mut err = self.fail(EncoderErrorKind::BadKeyType);
+                err.actual = Some(v.get_signature().to_string());
+                return Err(err);
+            }
         };
         Ok(())
     }
     fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
         let key : BasicValue = self.key.take().unwrap();
-        try!(f(self));
+        self.omittable = true;
+        let result = self.at(&key_path(&key), f);
+        self.omittable = false;
+        try!(result);
+        if self.omitted {
+            self.omitted = false;
+            return Ok(());
+        }
         let val : Value = self.val.pop().unwrap();
         let mut map = self.val.pop().unwrap();
         match map {
@@ -200,29 +674,122 @@ impl Encoder for DBusEncoder {
         Ok(())
     }
 
-    fn emit_option<F>(&mut self, _f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+    fn emit_option<F>(&mut self, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.none_omittable = std::mem::replace(&mut self.omittable, false);
+        f(self)
     }
     fn emit_option_none(&mut self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+        match self.option_encoding {
+            OptionEncoding::Array => self.emit_seq(0, |_| Ok(())),
+            OptionEncoding::Omit => {
+                if !self.none_omittable {
+                    return Err(self.fail(EncoderErrorKind::NoneOutsideDictionary));
+                }
+                self.omitted = true;
+                Ok(())
+            },
+            OptionEncoding::Maybe => {
+                if !self.allow_empty {
+                    return Err(self.fail(EncoderErrorKind::EmptyMaybe));
+                }
+                // The signature is filled in by conform
+                self.val.push(Value::Maybe(Maybe::new(None, "")));
+                Ok(())
+            },
+            OptionEncoding::Sentinel(ref x) => {
+                self.val.push(x.clone());
+                Ok(())
+            }
+        }
     }
-    fn emit_option_some<F>(&mut self, _f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+    fn emit_option_some<F>(&mut self, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        try!(f(self));
+        match self.option_encoding {
+            OptionEncoding::Array => self.handle_array(1),
+            OptionEncoding::Maybe => {
+                let v = self.val.pop().unwrap();
+                let sig = v.get_signature().to_string();
+                self.val.push(Value::Maybe(Maybe::new(Some(v), &sig)));
+                Ok(())
+            },
+            OptionEncoding::Omit | OptionEncoding::Sentinel(_) => Ok(())
+        }
     }
-    fn emit_enum<F>(&mut self, _name: &str, _f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+    fn emit_enum<F>(&mut self, name: &str, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.omittable = false;
+        if name == VALUE_ENUM_NAME {
+            let _stash = SlotGuard::set(&VALUE_STASH, Stash::Wanted);
+            try!(f(self));
+            if let Some(Stash::Filled(x)) = VALUE_STASH.with(|x| x.borrow_mut().take()) {
+                self.val.push(x);
+            }
+            return Ok(());
+        }
+        if name == TYPED_ENUM_NAME {
+            if let Some(hint) = TYPE_HINT.with(|x| x.borrow_mut().take()) {
+                let allow_empty = std::mem::replace(&mut self.allow_empty, true);
+                let result = f(self);
+                self.allow_empty = allow_empty;
+                try!(result);
+                let v = self.val.pop().unwrap();
+                let v = try!(conform(v, &self.option_encoding.resolve(&hint.t), &self.path));
+                if hint.variant {
+                    let sig = v.get_signature().to_string();
+                    self.val.push(Value::Variant(Variant::new(v, &sig)));
+                } else {
+                    self.val.push(v);
+                }
+                return Ok(());
+            }
+        }
+        let encoding = match self.enum_encodings.get(name) {
+            Some(x) => *x,
+            None => EnumEncoding::Discriminant
+        };
+        self.enum_stack.push(encoding);
+        let result = f(self);
+        self.enum_stack.pop();
+        result
     }
-    fn emit_enum_variant<F>(&mut self, _v_name: &str, _v_id: usize, _len: usize, _f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+    fn emit_enum_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        let encoding = match self.enum_stack.last() {
+            Some(x) => *x,
+            None => return Err(self.fail(EncoderErrorKind::Unsupported))
+        };
+        let tag = match encoding {
+            EnumEncoding::Discriminant => BasicValue::Uint32(v_id as u32),
+            EnumEncoding::Name | EnumEncoding::Variant => BasicValue::String(v_name.to_string()),
+        };
+        let contents = if len == 0 {
+            Value::BasicValue(tag.clone())
+        } else {
+            try!(f(self));
+            try!(self.handle_struct(len));
+            let fields = self.val.pop().unwrap();
+            let sig = fields.get_signature().to_string();
+            Value::Variant(Variant::new(fields, &sig))
+        };
+        match (encoding, len) {
+            (EnumEncoding::Variant, 0) => {
+                self.val.push(Value::Variant(Variant::new(contents, "s")));
+            },
+            (EnumEncoding::Variant, _) | (_, 0) => self.val.push(contents),
+            _ => {
+                self.val.push(Value::BasicValue(tag));
+                self.val.push(contents);
+                try!(self.handle_struct(2));
+            }
+        }
+        Ok(())
     }
-    fn emit_enum_variant_arg<F>(&mut self, _a_idx: usize, _f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+    fn emit_enum_variant_arg<F>(&mut self, a_idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.at(&format!(".{}", a_idx), f)
     }
-    fn emit_enum_struct_variant<F>(&mut self, _v_name: &str, _v_id: usize, _len: usize, _f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+    fn emit_enum_struct_variant<F>(&mut self, v_name: &str, v_id: usize, len: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.emit_enum_variant(v_name, v_id, len, f)
     }
-    fn emit_enum_struct_variant_field<F>(&mut self, _f_name: &str, _f_idx: usize, _f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
-        Err(EncoderError::Unsupported)
+    fn emit_enum_struct_variant_field<F>(&mut self, f_name: &str, _f_idx: usize, f: F) -> Result<(), Self::Error> where F: FnOnce(&mut Self) -> Result<(), Self::Error> {
+        self.at(&format!(".{}", f_name), f)
     }
 }
 
@@ -230,7 +797,7 @@ impl Encoder for DBusEncoder {
 mod test {
     use rustc_serialize::{Encoder,Encodable};
     use std::collections::BTreeMap;
-    use types::{Value,BasicValue,Struct,Signature,DictEntry,Dictionary,Array};
+    use types::{Value,BasicValue,Struct,Signature,DictEntry,Dictionary,Array,Path,Variant,Maybe};
     use encoder::*;
 
     #[test]
@@ -248,7 +815,9 @@ mod test {
     #[test]
     fn test_empty_array() {
         let array : Vec<u32> = vec![];
-        assert_eq!(DBusEncoder::encode(&array), Err(EncoderError::EmptyArray));
+        assert_eq!(DBusEncoder::encode(&array).err().unwrap().kind, EncoderErrorKind::EmptyArray);
+        assert_eq!(Value::try_from_encodable(&array).err().unwrap().kind, EncoderErrorKind::EmptyArray);
+        assert!(Value::try_from_encodable(&vec![1u32]).is_ok());
     }
 
     #[test]
@@ -302,19 +871,65 @@ mod test {
             },
         ];
         assert_eq!(v, Value::Dictionary(Dictionary::new(map2)));
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), Value::from(1u32));
+        map.insert("b".to_string(), Value::from("x"));
+        assert_eq!(DBusEncoder::encode(&map).err().unwrap(), EncoderError {
+            kind: EncoderErrorKind::TypeMismatch,
+            path: "{\"b\"}".to_string(),
+            expected: Some("u".to_string()),
+            actual: Some("s".to_string())
+        });
     }
 
     #[test]
     fn test_empty_map() {
         let map : BTreeMap<u32,u64> = BTreeMap::new();
-        assert_eq!(DBusEncoder::encode(&map), Err(EncoderError::EmptyMap));
+        assert_eq!(DBusEncoder::encode(&map).err().unwrap().kind, EncoderErrorKind::EmptyMap);
+    }
+
+    #[test]
+    fn test_encode_typed_empty() {
+        let array : Vec<u32> = vec![];
+        let v = DBusEncoder::encode_typed(&array).unwrap();
+        assert_eq!(v, Value::Array(Array::new_with_sig(vec![], "au".to_string())));
+
+        let nested : Vec<Vec<u8>> = vec![vec![], vec![1]];
+        let v = DBusEncoder::encode_typed(&nested).unwrap();
+        assert_eq!(v.get_signature(), "aay");
+        assert_eq!(v.validate(), Ok(()));
+
+        let mut map : BTreeMap<String,Vec<i64>> = BTreeMap::new();
+        let v = DBusEncoder::encode_typed(&map).unwrap();
+        assert_eq!(v, Value::Dictionary(Dictionary::new_with_sig(vec![], "a{sax}".to_string())));
+        map.insert("a".to_string(), vec![]);
+        let v = DBusEncoder::encode_typed(&map).unwrap();
+        assert_eq!(v.get_signature(), "a{sax}");
+        assert_eq!(v.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_encode_typed_tuple() {
+        let x = (Path("/a".to_string()), vec![Signature("s".to_string())], 3u8);
+        let v = DBusEncoder::encode_typed(&x).unwrap();
+        assert_eq!(v, Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::ObjectPath(Path("/a".to_string()))),
+                Value::Array(Array::new_with_sig(vec![
+                    Value::BasicValue(BasicValue::Signature(Signature("s".to_string())))
+                ], "ag".to_string())),
+                Value::BasicValue(BasicValue::Byte(3)),
+            ],
+            signature: Signature("(oagy)".to_string())
+        }));
     }
 
     #[test]
     fn test_bad_map_key() {
         let mut map : BTreeMap<(u32,u32),u32> = BTreeMap::new();
         map.insert((1,2), 100);
-        assert_eq!(DBusEncoder::encode(&map), Err(EncoderError::BadKeyType));
+        assert_eq!(DBusEncoder::encode(&map).err().unwrap().kind, EncoderErrorKind::BadKeyType);
     }
 
     #[test]
@@ -501,4 +1116,162 @@ mod test {
         };
         assert_eq!(v, Value::Struct(expected_struct));
     }
+
+    enum Shape {
+        Empty,
+        Circle(u32),
+    }
+
+    impl Encodable for Shape {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum("Shape", |s| {
+                match self {
+                    &Shape::Empty => s.emit_enum_variant("Empty", 0, 0, |_| Ok(())),
+                    &Shape::Circle(r) => s.emit_enum_variant("Circle", 1, 1, |s| {
+                        s.emit_enum_variant_arg(0, |s| r.encode(s))
+                    })
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_enum () {
+        let circle_args = Value::Struct(Struct {
+            objects: vec![Value::BasicValue(BasicValue::Uint32(3))],
+            signature: Signature("(u)".to_string())
+        });
+        let circle_variant = Value::Variant(Variant::new(circle_args.clone(), "(u)"));
+
+        let v = DBusEncoder::encode(&Shape::Empty).unwrap();
+        assert_eq!(v, Value::BasicValue(BasicValue::Uint32(0)));
+        let v = DBusEncoder::encode(&Shape::Circle(3)).unwrap();
+        assert_eq!(v, Value::Struct(Struct {
+            objects: vec![Value::BasicValue(BasicValue::Uint32(1)), circle_variant.clone()],
+            signature: Signature("(uv)".to_string())
+        }));
+
+        let mut encoder = DBusEncoder::new();
+        encoder.set_enum_encoding("Shape", EnumEncoding::Name);
+        let v = encoder.encode_value(&Shape::Empty).unwrap();
+        assert_eq!(v, Value::BasicValue(BasicValue::String("Empty".to_string())));
+        let v = encoder.encode_value(&Shape::Circle(3)).unwrap();
+        assert_eq!(v.get_signature(), "(sv)");
+
+        encoder.set_enum_encoding("Shape", EnumEncoding::Variant);
+        let v = encoder.encode_value(&Shape::Empty).unwrap();
+        assert_eq!(v, Value::Variant(Variant::new(Value::BasicValue(BasicValue::String("Empty".to_string())), "s")));
+        let v = encoder.encode_value(&Shape::Circle(3)).unwrap();
+        assert_eq!(v, circle_variant);
+    }
+
+    #[test]
+    fn test_option () {
+        let some = Some(3u32);
+        let none: Option<u32> = None;
+        let three = Value::BasicValue(BasicValue::Uint32(3));
+
+        let v = DBusEncoder::encode(&some).unwrap();
+        assert_eq!(v, Value::Array(Array::new(vec![three.clone()])));
+        assert_eq!(DBusEncoder::encode(&none).err().unwrap().kind, EncoderErrorKind::EmptyArray);
+        let v = DBusEncoder::encode_typed(&none).unwrap();
+        assert_eq!(v, Value::Array(Array::new_with_sig(vec![], "au".to_string())));
+
+        let mut encoder = DBusEncoder::new();
+        encoder.set_option_encoding(OptionEncoding::Maybe);
+        assert_eq!(encoder.encode_value(&some).unwrap(), Value::Maybe(Maybe::new(Some(three.clone()), "u")));
+        assert_eq!(encoder.encode_value(&none).err().unwrap().kind, EncoderErrorKind::EmptyMaybe);
+        assert_eq!(encoder.encode_value_typed(&(none, 1u8)).unwrap().get_signature(), "(muy)");
+
+        encoder.set_option_encoding(OptionEncoding::Sentinel(Value::BasicValue(BasicValue::Uint32(0))));
+        assert_eq!(encoder.encode_value(&some).unwrap(), three);
+        assert_eq!(encoder.encode_value(&none).unwrap(), Value::BasicValue(BasicValue::Uint32(0)));
+
+        encoder.set_option_encoding(OptionEncoding::Omit);
+        let mut props = BTreeMap::new();
+        props.insert("a".to_string(), some);
+        props.insert("b".to_string(), none);
+        let v = encoder.encode_value_typed(&props).unwrap();
+        assert_eq!(v, Value::Dictionary(Dictionary::new(vec![
+            DictEntry { key: BasicValue::String("a".to_string()), value: three.clone() },
+        ])));
+        assert_eq!(encoder.encode_value(&none).err().unwrap().kind, EncoderErrorKind::NoneOutsideDictionary);
+        let mut nested = BTreeMap::new();
+        nested.insert(1u8, (none,));
+        assert_eq!(encoder.encode_value(&nested).err().unwrap().kind, EncoderErrorKind::NoneOutsideDictionary);
+    }
+
+    #[test]
+    fn test_error_path () {
+        let mut map = BTreeMap::new();
+        map.insert("Tracks".to_string(), vec![vec![1u32], vec![]]);
+        let err = DBusEncoder::encode(&map).err().unwrap();
+        assert_eq!(err.path, "{\"Tracks\"}[1]");
+        assert_eq!(err.to_string(), "at {\"Tracks\"}[1]: cannot determine the signature of an empty array");
+
+        let x = vec![Value::from(1u32), Value::from(2u32), Value::from("x")];
+        let err = DBusEncoder::encode(&(1u8, x)).err().unwrap();
+        assert_eq!(err, EncoderError {
+            kind: EncoderErrorKind::TypeMismatch,
+            path: ".1[2]".to_string(),
+            expected: Some("u".to_string()),
+            actual: Some("s".to_string())
+        });
+        assert_eq!(err.to_string(), "at .1[2]: expected a value of signature 'u', found 's'");
+
+        let mut map = BTreeMap::new();
+        map.insert((1u8,), 1u8);
+        let err = DBusEncoder::encode(&map).err().unwrap();
+        assert_eq!(err.to_string(), "dictionary keys must be basic types, found '(y)'");
+    }
+
+    #[test]
+    fn test_value () {
+        let path = Value::BasicValue(BasicValue::ObjectPath(Path("/a".to_string())));
+        let empty = Value::Array(Array::new_with_sig(vec![], "as".to_string()));
+        let variant = Variant::new(path.clone(), "o");
+        let v = DBusEncoder::encode(&(1u8, vec![empty.clone(), empty.clone()], variant.clone())).unwrap();
+        assert_eq!(v, Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::Byte(1)),
+                Value::Array(Array::new_with_sig(vec![empty.clone(), empty.clone()], "aas".to_string())),
+                Value::Variant(variant.clone()),
+            ],
+            signature: Signature("(yaasv)".to_string())
+        }));
+        assert_eq!(DBusEncoder::encode(&vec![path.clone(), empty]).err().unwrap().kind, EncoderErrorKind::TypeMismatch);
+        assert_eq!(Value::try_from_encodable(&vec![path.clone()]).unwrap(), Value::Array(Array::new(vec![path.clone()])));
+
+        // Other encoders are given the plain data
+        let v = Value::Struct(Struct {
+            objects: vec![path, Value::Variant(Variant::new(Value::from(2u32), "u"))],
+            signature: Signature("(ov)".to_string())
+        });
+        assert_eq!(::rustc_serialize::json::encode(&v).unwrap(), "[\"/a\",2]");
+    }
+
+    /// Starts to encode a Value, then fails
+    struct GivesUp;
+
+    impl Encodable for GivesUp {
+        fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+            s.emit_enum(VALUE_ENUM_NAME, |s| s.emit_map(0, |_| Ok(())))
+        }
+    }
+
+    #[test]
+    fn test_no_stale_value () {
+        assert_eq!(DBusEncoder::encode(&GivesUp).err().unwrap().kind, EncoderErrorKind::EmptyMap);
+        assert!(VALUE_STASH.with(|x| x.borrow().is_none()));
+        assert_eq!(::rustc_serialize::json::encode(&Value::from(1u32)).unwrap(), "1");
+
+        // A Value inside a Value's container is stashed by the nested call, not the outer one
+        let inner = Value::Struct(Struct {
+            objects: vec![Value::from(1u32)],
+            signature: Signature("(u)".to_string())
+        });
+        let v = DBusEncoder::encode(&(vec![inner.clone()], inner.clone())).unwrap();
+        assert_eq!(v.get_signature(), "(a(u)(u))");
+        assert!(VALUE_STASH.with(|x| x.borrow().is_none()));
+    }
 }
@@ -0,0 +1,108 @@
+//! A single error type for converting between Rust values and Value in either direction, so that
+//! code which both encodes and decodes can use `?` with one Result type.
+#![allow(deprecated)]
+use std::error;
+use std::fmt;
+
+#[cfg(feature = "rustc-serialize")]
+use decoder::DecodeError;
+#[cfg(feature = "rustc-serialize")]
+use encoder::EncoderError;
+#[cfg(feature = "serde")]
+use serializer::SerializeError;
+#[cfg(feature = "serde")]
+use deserializer::DeserializeError;
+
+#[derive(Debug,PartialEq)]
+pub enum Error {
+    /// DBusEncoder failed
+    #[cfg(feature = "rustc-serialize")]
+    Encode(EncoderError),
+    /// DBusDecoder failed
+    #[cfg(feature = "rustc-serialize")]
+    Decode(DecodeError),
+    #[cfg(feature = "serde")]
+    Serialize(SerializeError),
+    #[cfg(feature = "serde")]
+    Deserialize(DeserializeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "rustc-serialize")]
+            Error::Encode(ref x) => write!(f, "encoding failed: {}", x),
+            #[cfg(feature = "rustc-serialize")]
+            Error::Decode(ref x) => write!(f, "decoding failed: {}", x),
+            #[cfg(feature = "serde")]
+            Error::Serialize(ref x) => write!(f, "serialization failed: {}", x),
+            #[cfg(feature = "serde")]
+            Error::Deserialize(ref x) => write!(f, "deserialization failed: {}", x),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            #[cfg(feature = "rustc-serialize")]
+            Error::Encode(ref x) => Some(x),
+            #[cfg(feature = "rustc-serialize")]
+            Error::Decode(ref x) => Some(x),
+            #[cfg(feature = "serde")]
+            Error::Serialize(ref x) => Some(x),
+            #[cfg(feature = "serde")]
+            Error::Deserialize(ref x) => Some(x),
+        }
+    }
+}
+
+#[cfg(feature = "rustc-serialize")]
+impl From<EncoderError> for Error {
+    fn from(x: EncoderError) -> Error {
+        Error::Encode(x)
+    }
+}
+
+#[cfg(feature = "rustc-serialize")]
+impl From<DecodeError> for Error {
+    fn from(x: DecodeError) -> Error {
+        Error::Decode(x)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializeError> for Error {
+    fn from(x: SerializeError) -> Error {
+        Error::Serialize(x)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<DeserializeError> for Error {
+    fn from(x: DeserializeError) -> Error {
+        Error::Deserialize(x)
+    }
+}
+
+#[cfg(all(test, feature = "rustc-serialize"))]
+mod test {
+    use std::error::Error as StdError;
+    use encoder::DBusEncoder;
+    use decoder::DBusDecoder;
+    use error::*;
+
+    fn round_trip (x: &Vec<u32>) -> Result<Vec<String>, Error> {
+        let v = try!(DBusEncoder::encode(x));
+        Ok(try!(DBusDecoder::decode(v)))
+    }
+
+    #[test]
+    fn test_error () {
+        let err = round_trip(&vec![]).err().unwrap();
+        assert_eq!(err.to_string(), "encoding failed: cannot determine the signature of an empty array");
+        let err = round_trip(&vec![1]).err().unwrap();
+        assert_eq!(err.to_string(), "decoding failed: at [0]: cannot decode a value of signature 'u' as String");
+        assert!(err.source().is_some());
+    }
+}
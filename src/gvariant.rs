@@ -0,0 +1,650 @@
+//! Converts Value objects to and from GLib's GVariant serialization format.  GVariant uses the
+//! same type system as D-Bus, but containers are framed with offset tables at their end rather
+//! than with length prefixes, and alignment is relative to the start of the containing value.
+#![allow(deprecated, clippy::legacy_numeric_constants, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
+use std;
+
+use types::{Value,BasicValue,Path,Signature,Struct,Variant,Array,Dictionary,DictEntry,Maybe};
+use signature::{SignatureType,BasicType};
+use wire::Endianness;
+
+/// Maximum nesting depth of containers (including variants) accepted when deserializing
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug,PartialEq)]
+pub enum GVariantError {
+    BadSignature,
+    StringContainsNul,
+    BadSize,
+    BadFramingOffset,
+    InvalidUtf8,
+    InvalidBoolean,
+    TooDeep,
+}
+
+fn parse_type (sig: &str) -> Result<SignatureType,GVariantError> {
+    SignatureType::parse_gvariant(sig).map_err(|_| GVariantError::BadSignature)
+}
+
+fn align_up (pos: usize, align: usize) -> usize {
+    pos.div_ceil(align) * align
+}
+
+fn basic_alignment (t: BasicType) -> usize {
+    match t {
+        BasicType::Byte | BasicType::Boolean | BasicType::String | BasicType::ObjectPath |
+            BasicType::Signature => 1,
+        BasicType::Int16 | BasicType::Uint16 => 2,
+        BasicType::Int32 | BasicType::Uint32 | BasicType::UnixFd => 4,
+        BasicType::Int64 | BasicType::Uint64 | BasicType::Double => 8,
+    }
+}
+
+/// Returns the alignment of a struct or dict entry with the given members
+fn struct_alignment (members: &[&SignatureType]) -> usize {
+    members.iter().map(|m| alignment(m)).max().unwrap_or(1)
+}
+
+/// Returns the alignment of the given type
+fn alignment (t: &SignatureType) -> usize {
+    match t {
+        &SignatureType::Basic(x) => basic_alignment(x),
+        &SignatureType::Variant => 8,
+        &SignatureType::Array(ref elem) | &SignatureType::Maybe(ref elem) => alignment(elem),
+        &SignatureType::Dict(key, ref value) => {
+            std::cmp::max(basic_alignment(key), alignment(value))
+        },
+        &SignatureType::Struct(ref fields) => {
+            let members : Vec<&SignatureType> = fields.iter().collect();
+            struct_alignment(&members)
+        },
+    }
+}
+
+/// Returns the serialized size of a struct or dict entry with the given members, or None if
+/// values of the type can have differing sizes
+fn struct_fixed_size (members: &[&SignatureType]) -> Option<usize> {
+    if members.is_empty() {
+        // The unit type is serialized as a single zero byte
+        return Some(1);
+    }
+    let mut size = 0;
+    for m in members.iter() {
+        size = align_up(size, alignment(m));
+        size += fixed_size(m)?;
+    }
+    Some(align_up(size, struct_alignment(members)))
+}
+
+/// Returns the serialized size of the given type, or None if values of the type can have
+/// differing sizes
+fn fixed_size (t: &SignatureType) -> Option<usize> {
+    match t {
+        &SignatureType::Basic(x) => match x {
+            BasicType::String | BasicType::ObjectPath | BasicType::Signature => None,
+            _ => Some(basic_alignment(x))
+        },
+        &SignatureType::Variant | &SignatureType::Array(_) | &SignatureType::Dict(_, _) |
+            &SignatureType::Maybe(_) => None,
+        &SignatureType::Struct(ref fields) => {
+            let members : Vec<&SignatureType> = fields.iter().collect();
+            struct_fixed_size(&members)
+        },
+    }
+}
+
+/// Returns the size of each framing offset for a container with the given total size
+fn offset_size (size: usize) -> usize {
+    if size > std::u32::MAX as usize {
+        8
+    } else if size > std::u16::MAX as usize {
+        4
+    } else if size > std::u8::MAX as usize {
+        2
+    } else if size > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Appends framing offsets to a container whose contents are in buf
+fn write_offsets (buf: &mut Vec<u8>, offsets: &[usize]) {
+    let body = buf.len();
+    let n = offsets.len();
+    let size = if body + n <= std::u8::MAX as usize {
+        1
+    } else if body + 2 * n <= std::u16::MAX as usize {
+        2
+    } else if body + 4 * n <= std::u32::MAX as usize {
+        4
+    } else {
+        8
+    };
+    // Framing offsets are always little endian
+    for offset in offsets {
+        buf.extend_from_slice(&(*offset as u64).to_le_bytes()[..size]);
+    }
+}
+
+fn read_offset (data: &[u8], pos: usize, size: usize) -> usize {
+    let mut bytes = [0; 8];
+    bytes[..size].copy_from_slice(&data[pos..pos+size]);
+    u64::from_le_bytes(bytes) as usize
+}
+
+fn pad (buf: &mut Vec<u8>, align: usize) {
+    while !buf.len().is_multiple_of(align) {
+        buf.push(0);
+    }
+}
+
+fn serialize_basic (v: &BasicValue, endian: Endianness) -> Result<Vec<u8>,GVariantError> {
+    macro_rules! number {
+        ($x:expr) => (match endian {
+            Endianness::Little => $x.to_le_bytes().to_vec(),
+            Endianness::Big => $x.to_be_bytes().to_vec(),
+        })
+    }
+
+    let bytes = match v {
+        &BasicValue::Byte(x) => vec![x],
+        &BasicValue::Boolean(x) => vec![x as u8],
+        &BasicValue::Double(x) => number!(x.to_bits()),
+        &BasicValue::Int16(x) => number!(x),
+        &BasicValue::Uint16(x) => number!(x),
+        &BasicValue::Int32(x) => number!(x),
+        &BasicValue::Uint32(x) => number!(x),
+        &BasicValue::Int64(x) => number!(x),
+        &BasicValue::Uint64(x) => number!(x),
+        &BasicValue::String(ref x) => try!(serialize_str(x)),
+        &BasicValue::ObjectPath(ref x) => try!(serialize_str(&x.0)),
+        &BasicValue::Signature(ref x) => try!(serialize_str(&x.0)),
+    };
+    Ok(bytes)
+}
+
+fn serialize_str (s: &str) -> Result<Vec<u8>,GVariantError> {
+    if s.as_bytes().contains(&0) {
+        return Err(GVariantError::StringContainsNul);
+    }
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.push(0);
+    Ok(bytes)
+}
+
+/// Serializes a sequence of already-serialized elements.  align and fixed describe the element
+/// type.
+fn serialize_array<I> (elems: I, align: usize, fixed: bool) -> Result<Vec<u8>,GVariantError>
+        where I: Iterator<Item=Result<Vec<u8>,GVariantError>> {
+    let mut buf = Vec::new();
+    let mut offsets = Vec::new();
+    for elem in elems {
+        pad(&mut buf, align);
+        buf.extend_from_slice(&try!(elem));
+        offsets.push(buf.len());
+    }
+    if !fixed {
+        write_offsets(&mut buf, &offsets);
+    }
+    Ok(buf)
+}
+
+/// Serializes the members of a struct or dict entry
+fn serialize_struct (objects: &[&Value], members: &[&SignatureType], endian: Endianness)
+        -> Result<Vec<u8>,GVariantError> {
+    if objects.len() != members.len() {
+        return Err(GVariantError::BadSignature);
+    }
+    if objects.is_empty() {
+        return Ok(vec![0]);
+    }
+
+    let mut buf = Vec::new();
+    let mut offsets = Vec::new();
+    for (i, (obj, t)) in objects.iter().zip(members.iter()).enumerate() {
+        pad(&mut buf, alignment(t));
+        buf.extend_from_slice(&try!(serialize_value(obj, t, endian)));
+        if fixed_size(t).is_none() && i != objects.len() - 1 {
+            offsets.push(buf.len());
+        }
+    }
+
+    if struct_fixed_size(members).is_some() {
+        pad(&mut buf, struct_alignment(members));
+    } else {
+        // Offsets are stored in reverse order, so the first one is at the very end
+        offsets.reverse();
+        write_offsets(&mut buf, &offsets);
+    }
+    Ok(buf)
+}
+
+fn serialize_value (v: &Value, t: &SignatureType, endian: Endianness) -> Result<Vec<u8>,GVariantError> {
+    match (v, t) {
+        (&Value::BasicValue(ref x), &SignatureType::Basic(bt)) => {
+            if !x.get_signature().starts_with(bt.code()) {
+                return Err(GVariantError::BadSignature);
+            }
+            serialize_basic(x, endian)
+        },
+        (&Value::Array(ref x), &SignatureType::Array(ref elem)) => {
+            let elems = x.objects.iter().map(|obj| serialize_value(obj, elem, endian));
+            serialize_array(elems, alignment(elem), fixed_size(elem).is_some())
+        },
+        (&Value::Dictionary(ref x), &SignatureType::Dict(key, ref value)) => {
+            let key_type = SignatureType::Basic(key);
+            let members = [&key_type, &**value];
+            let elems = x.entries.iter().map(|entry| {
+                let key = Value::BasicValue(entry.key.clone());
+                serialize_struct(&[&key, &entry.value], &members, endian)
+            });
+            serialize_array(elems, struct_alignment(&members), struct_fixed_size(&members).is_some())
+        },
+        (&Value::Struct(ref x), &SignatureType::Struct(ref fields)) => {
+            let objects : Vec<&Value> = x.objects.iter().collect();
+            let members : Vec<&SignatureType> = fields.iter().collect();
+            serialize_struct(&objects, &members, endian)
+        },
+        (&Value::Variant(ref x), &SignatureType::Variant) => {
+            let inner_type = try!(parse_type(&x.signature.0));
+            let mut buf = try!(serialize_value(&x.object, &inner_type, endian));
+            buf.push(0);
+            buf.extend_from_slice(x.signature.0.as_bytes());
+            Ok(buf)
+        },
+        (&Value::Maybe(ref x), &SignatureType::Maybe(ref inner_type)) => {
+            let object = match x.object {
+                Some(ref o) => o,
+                None => return Ok(Vec::new())
+            };
+            let mut buf = try!(serialize_value(object, inner_type, endian));
+            // A zero byte tells a non-empty value of variable size apart from Nothing
+            if fixed_size(inner_type).is_none() {
+                buf.push(0);
+            }
+            Ok(buf)
+        },
+        _ => Err(GVariantError::BadSignature)
+    }
+}
+
+/// Serializes a value in GVariant format
+pub fn serialize(v: &Value, endian: Endianness) -> Result<Vec<u8>,GVariantError> {
+    serialize_value(v, &try!(parse_type(v.get_signature())), endian)
+}
+
+struct Deserializer {
+    endian: Endianness,
+    depth: usize
+}
+
+impl Deserializer {
+    fn read_str (data: &[u8]) -> Result<String,GVariantError> {
+        match data.split_last() {
+            Some((&0, s)) => {
+                if s.contains(&0) {
+                    return Err(GVariantError::StringContainsNul);
+                }
+                match std::str::from_utf8(s) {
+                    Ok(x) => Ok(x.to_string()),
+                    Err(_) => Err(GVariantError::InvalidUtf8)
+                }
+            },
+            _ => Err(GVariantError::BadSize)
+        }
+    }
+
+    fn read_basic (&self, data: &[u8], t: BasicType) -> Result<BasicValue,GVariantError> {
+        macro_rules! number {
+            ($t:ty) => ({
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                if data.len() != bytes.len() {
+                    return Err(GVariantError::BadSize);
+                }
+                bytes.copy_from_slice(data);
+                match self.endian {
+                    Endianness::Little => <$t>::from_le_bytes(bytes),
+                    Endianness::Big => <$t>::from_be_bytes(bytes),
+                }
+            })
+        }
+
+        let val = match t {
+            BasicType::Byte => BasicValue::Byte(number!(u8)),
+            BasicType::Boolean => match number!(u8) {
+                0 => BasicValue::Boolean(false),
+                1 => BasicValue::Boolean(true),
+                _ => return Err(GVariantError::InvalidBoolean)
+            },
+            BasicType::Double => BasicValue::Double(f64::from_bits(number!(u64))),
+            BasicType::Int16 => BasicValue::Int16(number!(i16)),
+            BasicType::Uint16 => BasicValue::Uint16(number!(u16)),
+            BasicType::Int32 => BasicValue::Int32(number!(i32)),
+            BasicType::Uint32 => BasicValue::Uint32(number!(u32)),
+            BasicType::Int64 => BasicValue::Int64(number!(i64)),
+            BasicType::Uint64 => BasicValue::Uint64(number!(u64)),
+            BasicType::String => BasicValue::String(try!(Deserializer::read_str(data))),
+            BasicType::ObjectPath => BasicValue::ObjectPath(Path(try!(Deserializer::read_str(data)))),
+            BasicType::Signature => BasicValue::Signature(Signature(try!(Deserializer::read_str(data)))),
+            BasicType::UnixFd => return Err(GVariantError::BadSignature)
+        };
+        Ok(val)
+    }
+
+    /// Splits the serialized form of an array into the serialized form of each element.  align
+    /// and fixed describe the element type.
+    fn split_array (data: &[u8], align: usize, fixed: Option<usize>) -> Result<Vec<&[u8]>,GVariantError> {
+        let mut elems = Vec::new();
+        if let Some(size) = fixed {
+            if !data.len().is_multiple_of(size) {
+                return Err(GVariantError::BadSize);
+            }
+            for chunk in data.chunks(size) {
+                elems.push(chunk);
+            }
+            return Ok(elems);
+        }
+
+        if data.is_empty() {
+            return Ok(elems);
+        }
+        let osz = offset_size(data.len());
+        let offsets_start = read_offset(data, data.len() - osz, osz);
+        if offsets_start > data.len() || !(data.len() - offsets_start).is_multiple_of(osz) {
+            return Err(GVariantError::BadFramingOffset);
+        }
+
+        let mut start = 0;
+        let mut pos = offsets_start;
+        while pos < data.len() {
+            let end = read_offset(data, pos, osz);
+            start = align_up(start, align);
+            if start > end || end > offsets_start {
+                return Err(GVariantError::BadFramingOffset);
+            }
+            elems.push(&data[start..end]);
+            start = end;
+            pos += osz;
+        }
+        Ok(elems)
+    }
+
+    /// Splits the serialized form of a struct or dict entry into the serialized form of each
+    /// member
+    fn split_struct<'a> (data: &'a [u8], members: &[&SignatureType]) -> Result<Vec<&'a [u8]>,GVariantError> {
+        if let Some(size) = struct_fixed_size(members) {
+            if data.len() != size {
+                return Err(GVariantError::BadSize);
+            }
+        }
+
+        let osz = offset_size(data.len());
+        let mut offsets_end = data.len();
+        let mut pos = 0;
+        let mut fields = Vec::new();
+        for (i, t) in members.iter().enumerate() {
+            pos = align_up(pos, alignment(t));
+            let end = match fixed_size(t) {
+                Some(size) => pos + size,
+                None if i == members.len() - 1 => offsets_end,
+                None => {
+                    if offsets_end < osz {
+                        return Err(GVariantError::BadFramingOffset);
+                    }
+                    offsets_end -= osz;
+                    read_offset(data, offsets_end, osz)
+                }
+            };
+            if pos > end || end > offsets_end {
+                return Err(GVariantError::BadFramingOffset);
+            }
+            fields.push(&data[pos..end]);
+            pos = end;
+        }
+        Ok(fields)
+    }
+
+    fn read_container (&mut self, data: &[u8], t: &SignatureType) -> Result<Value,GVariantError> {
+        match t {
+            &SignatureType::Basic(x) => Ok(Value::BasicValue(try!(self.read_basic(data, x)))),
+            &SignatureType::Dict(key, ref value) => {
+                let key_type = SignatureType::Basic(key);
+                let members = [&key_type, &**value];
+                let mut entries = Vec::new();
+                let elems = try!(Deserializer::split_array(data, struct_alignment(&members),
+                                                           struct_fixed_size(&members)));
+                for elem in elems {
+                    let fields = try!(Deserializer::split_struct(elem, &members));
+                    entries.push(DictEntry {
+                        key: try!(self.read_basic(fields[0], key)),
+                        value: try!(self.read_type(fields[1], value))
+                    });
+                }
+                Ok(Value::Dictionary(Dictionary::new_with_sig(entries, t.to_string())))
+            },
+            &SignatureType::Array(ref elem_type) => {
+                let mut objects = Vec::new();
+                let elems = try!(Deserializer::split_array(data, alignment(elem_type), fixed_size(elem_type)));
+                for elem in elems {
+                    objects.push(try!(self.read_type(elem, elem_type)));
+                }
+                Ok(Value::Array(Array::new_with_sig(objects, t.to_string())))
+            },
+            &SignatureType::Struct(ref fields) => {
+                let members : Vec<&SignatureType> = fields.iter().collect();
+                let mut objects = Vec::new();
+                let field_data = try!(Deserializer::split_struct(data, &members));
+                for (field, field_type) in field_data.iter().zip(members.iter()) {
+                    objects.push(try!(self.read_type(field, field_type)));
+                }
+                Ok(Value::Struct(Struct {
+                    objects: objects,
+                    signature: Signature(t.to_string())
+                }))
+            },
+            &SignatureType::Variant => {
+                let sep = match data.iter().rposition(|x| *x == 0) {
+                    Some(x) => x,
+                    None => return Err(GVariantError::BadSize)
+                };
+                let inner_sig = match std::str::from_utf8(&data[sep+1..]) {
+                    Ok(x) => x,
+                    Err(_) => return Err(GVariantError::BadSignature)
+                };
+                let inner_type = try!(parse_type(inner_sig));
+                if inner_type.validate().is_err() {
+                    return Err(GVariantError::BadSignature);
+                }
+                let object = try!(self.read_type(&data[..sep], &inner_type));
+                Ok(Value::Variant(Variant::new(object, inner_sig)))
+            },
+            &SignatureType::Maybe(ref inner_type) => {
+                let inner_sig = inner_type.to_string();
+                if data.is_empty() {
+                    return Ok(Value::Maybe(Maybe::new(None, &inner_sig)));
+                }
+                let inner_data = match fixed_size(inner_type) {
+                    Some(_) => data,
+                    None => match data.split_last() {
+                        Some((&0, x)) => x,
+                        _ => return Err(GVariantError::BadSize)
+                    }
+                };
+                let object = try!(self.read_type(inner_data, inner_type));
+                Ok(Value::Maybe(Maybe::new(Some(object), &inner_sig)))
+            },
+        }
+    }
+
+    fn read_type (&mut self, data: &[u8], t: &SignatureType) -> Result<Value,GVariantError> {
+        if t.is_basic() {
+            return self.read_container(data, t);
+        }
+        if self.depth == MAX_DEPTH {
+            return Err(GVariantError::TooDeep);
+        }
+        self.depth += 1;
+        let result = self.read_container(data, t);
+        self.depth -= 1;
+        result
+    }
+}
+
+/// Deserializes a GVariant of type sig.  data must contain exactly the serialized value.
+pub fn deserialize(data: &[u8], sig: &str, endian: Endianness) -> Result<Value,GVariantError> {
+    let mut deserializer = Deserializer {
+        endian: endian,
+        depth: 0
+    };
+    deserializer.read_type(data, &try!(parse_type(sig)))
+}
+
+#[cfg(test)]
+mod test {
+    use types::{Value,BasicValue,Struct,Signature,Variant,Array,Dictionary,DictEntry,Maybe};
+    use wire::Endianness;
+    use gvariant::*;
+
+    fn string (s: &str) -> Value {
+        Value::BasicValue(BasicValue::String(s.to_string()))
+    }
+
+    fn int32 (x: i32) -> Value {
+        Value::BasicValue(BasicValue::Int32(x))
+    }
+
+    #[test]
+    fn test_string_array () {
+        let v = Value::Array(Array::new(vec![
+            string("i"), string("can"), string("has"), string("strings?"),
+        ]));
+        let bytes = serialize(&v, Endianness::Little).unwrap();
+        let mut expected = b"i\0can\0has\0strings?\0".to_vec();
+        expected.extend_from_slice(&[0x02, 0x06, 0x0a, 0x13]);
+        assert_eq!(bytes, expected);
+        assert_eq!(deserialize(&bytes, "as", Endianness::Little).unwrap(), v);
+    }
+
+    #[test]
+    fn test_struct () {
+        let v = Value::Struct(Struct {
+            objects: vec![string("foo"), int32(-1)],
+            signature: Signature("(si)".to_string())
+        });
+        let bytes = serialize(&v, Endianness::Little).unwrap();
+        assert_eq!(bytes, vec![b'f', b'o', b'o', 0, 0xff, 0xff, 0xff, 0xff, 0x04]);
+        assert_eq!(deserialize(&bytes, "(si)", Endianness::Little).unwrap(), v);
+    }
+
+    #[test]
+    fn test_struct_array () {
+        let v = Value::Array(Array::new(vec![
+            Value::Struct(Struct {
+                objects: vec![string("hi"), int32(-2)],
+                signature: Signature("(si)".to_string())
+            }),
+            Value::Struct(Struct {
+                objects: vec![string("bye"), int32(-1)],
+                signature: Signature("(si)".to_string())
+            }),
+        ]));
+        let bytes = serialize(&v, Endianness::Little).unwrap();
+        assert_eq!(bytes, vec![
+            0x68, 0x69, 0x00, 0x00, 0xfe, 0xff, 0xff, 0xff, 0x03, 0x00, 0x00, 0x00,
+            0x62, 0x79, 0x65, 0x00, 0xff, 0xff, 0xff, 0xff, 0x04, 0x09, 0x15,
+        ]);
+        assert_eq!(deserialize(&bytes, "a(si)", Endianness::Little).unwrap(), v);
+    }
+
+    #[test]
+    fn test_fixed_size () {
+        let v = Value::Struct(Struct {
+            objects: vec![Value::BasicValue(BasicValue::Byte(1)), int32(2)],
+            signature: Signature("(yi)".to_string())
+        });
+        let bytes = serialize(&v, Endianness::Big).unwrap();
+        assert_eq!(bytes, vec![1, 0, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(deserialize(&bytes, "(yi)", Endianness::Big).unwrap(), v);
+
+        let v = Value::Array(Array::new(vec![int32(1), int32(2)]));
+        let bytes = serialize(&v, Endianness::Little).unwrap();
+        assert_eq!(bytes, vec![1, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(deserialize(&bytes, "ai", Endianness::Little).unwrap(), v);
+        assert_eq!(deserialize(&bytes[1..], "ai", Endianness::Little), Err(GVariantError::BadSize));
+    }
+
+    #[test]
+    fn test_variant_dictionary () {
+        let v = Value::Dictionary(Dictionary::new(vec![
+            DictEntry {
+                key: BasicValue::String("answer".to_string()),
+                value: Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(42)), "u")),
+            },
+            DictEntry {
+                key: BasicValue::String("enabled".to_string()),
+                value: Value::Variant(Variant::new(Value::BasicValue(BasicValue::Boolean(true)), "b")),
+            },
+        ]));
+        for &endian in &[Endianness::Little, Endianness::Big] {
+            let bytes = serialize(&v, endian).unwrap();
+            assert_eq!(deserialize(&bytes, "a{sv}", endian).unwrap(), v);
+        }
+
+        let v = Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(7)), "u"));
+        assert_eq!(serialize(&v, Endianness::Little).unwrap(), vec![7, 0, 0, 0, 0, b'u']);
+    }
+
+    #[test]
+    fn test_empty () {
+        let v = Value::Array(Array::new_with_sig(vec![], "as".to_string()));
+        assert_eq!(serialize(&v, Endianness::Little).unwrap(), vec![]);
+        assert_eq!(deserialize(&[], "as", Endianness::Little).unwrap(), v);
+
+        let v = Value::Struct(Struct {
+            objects: vec![],
+            signature: Signature("()".to_string())
+        });
+        assert_eq!(serialize(&v, Endianness::Little).unwrap(), vec![0]);
+        assert_eq!(deserialize(&[0], "()", Endianness::Little).unwrap(), v);
+    }
+
+    #[test]
+    fn test_bad_framing () {
+        let bytes = vec![b'a', 0, 0x05];
+        assert_eq!(deserialize(&bytes, "as", Endianness::Little), Err(GVariantError::BadFramingOffset));
+    }
+
+    #[test]
+    fn test_deep_variant_signature () {
+        let mut bytes = vec![0];
+        bytes.extend("a".repeat(1_000_000).into_bytes());
+        bytes.push(b'y');
+        assert_eq!(deserialize(&bytes, "v", Endianness::Little), Err(GVariantError::BadSignature));
+
+        let mut bytes = vec![0];
+        bytes.extend("a".repeat(33).into_bytes());
+        bytes.push(b'y');
+        assert_eq!(deserialize(&bytes, "v", Endianness::Little), Err(GVariantError::BadSignature));
+    }
+
+    #[test]
+    fn test_maybe () {
+        let v = Value::Maybe(Maybe::new(Some(int32(5)), "i"));
+        let bytes = serialize(&v, Endianness::Little).unwrap();
+        assert_eq!(bytes, vec![5, 0, 0, 0]);
+        assert_eq!(deserialize(&bytes, "mi", Endianness::Little).unwrap(), v);
+
+        let v = Value::Maybe(Maybe::new(Some(string("hi")), "s"));
+        let bytes = serialize(&v, Endianness::Little).unwrap();
+        assert_eq!(bytes, b"hi\0\0".to_vec());
+        assert_eq!(deserialize(&bytes, "ms", Endianness::Little).unwrap(), v);
+
+        let v = Value::Maybe(Maybe::new(None, "s"));
+        assert_eq!(serialize(&v, Endianness::Little).unwrap(), vec![]);
+        assert_eq!(deserialize(&[], "ms", Endianness::Little).unwrap(), v);
+
+        assert_eq!(deserialize(&[1, 0], "mi", Endianness::Little), Err(GVariantError::BadSize));
+        assert_eq!(deserialize(b"hi\0", "ms", Endianness::Little), Err(GVariantError::BadSize));
+    }
+}
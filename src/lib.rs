@@ -1,8 +1,46 @@
 //! Central to this crate is the Value enum.  Value can be used to express any valid D-Bus data
-//! structure (and some invalid ones).  Additionally, rustc_serialize can be used to convert from
-//! standard rust data types to Value, and vice-versa.
+//! structure (and some invalid ones).  Additionally, rustc_serialize or serde can be used to
+//! convert from standard rust data types to Value, and vice-versa.  The wire and gvariant modules convert
+//! Values to and from the binary D-Bus and GVariant formats respectively.
+//!
+//! The types, signature and names modules have no dependencies.  Everything else sits behind a
+//! cargo feature, all of which are enabled by default:
+//!
+//! * rustc-serialize: the encoder, decoder and typed modules
+//! * serde: the serializer and deserializer modules
+//! * wire: the wire, gvariant and message modules
+//!
+//! With both serde and wire, the wire_serde module converts Rust values directly to and from the
+//! wire format.  The error module wraps the errors of the rustc-serialize and serde conversions
+//! in one Error type.
+#[cfg(feature = "rustc-serialize")]
 extern crate rustc_serialize;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
 pub mod types;
+pub mod signature;
+pub mod names;
+#[cfg(any(feature = "rustc-serialize", feature = "serde"))]
+pub mod error;
+#[cfg(feature = "rustc-serialize")]
 pub mod decoder;
+#[cfg(feature = "rustc-serialize")]
 pub mod encoder;
+#[cfg(feature = "rustc-serialize")]
+pub mod typed;
+#[cfg(feature = "serde")]
+pub mod serializer;
+#[cfg(feature = "serde")]
+pub mod deserializer;
+#[cfg(feature = "wire")]
+pub mod wire;
+#[cfg(feature = "wire")]
+pub mod gvariant;
+#[cfg(feature = "wire")]
+pub mod message;
+#[cfg(all(feature = "serde", feature = "wire"))]
+pub mod wire_serde;
@@ -0,0 +1,534 @@
+//! Contains the Message type, which models a complete D-Bus message: the fixed header, the header
+//! fields and a body made up of Values.  Messages can be converted to and from the wire format.
+#![allow(deprecated, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
+use types::{Value,BasicValue,Path,Signature,Struct,Variant,Array};
+use signature::{self,SignatureType};
+use names::{BusName,InterfaceName,MemberName,ErrorName};
+use wire::{Endianness,WireEncoder,WireDecoder,WireError};
+
+/// Version of the D-Bus protocol implemented by this module
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Maximum length in bytes of a complete message, as set by the D-Bus specification
+pub const MAX_MESSAGE_LEN: usize = 134217728;
+
+/// The caller does not expect a reply to this method call
+pub const FLAG_NO_REPLY_EXPECTED: u8 = 0x1;
+/// The bus should not launch an owner for the destination name in response to this message
+pub const FLAG_NO_AUTO_START: u8 = 0x2;
+/// The caller is prepared to wait for interactive authorization
+pub const FLAG_ALLOW_INTERACTIVE_AUTHORIZATION: u8 = 0x4;
+
+const HEADER_SIGNATURE: &str = "(yyyyuua(yv))";
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum MessageType {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+impl MessageType {
+    fn to_byte(self) -> u8 {
+        match self {
+            MessageType::MethodCall => 1,
+            MessageType::MethodReturn => 2,
+            MessageType::Error => 3,
+            MessageType::Signal => 4,
+        }
+    }
+
+    fn from_byte(x: u8) -> Option<MessageType> {
+        match x {
+            1 => Some(MessageType::MethodCall),
+            2 => Some(MessageType::MethodReturn),
+            3 => Some(MessageType::Error),
+            4 => Some(MessageType::Signal),
+            _ => None
+        }
+    }
+}
+
+/// A single entry in the header field array
+#[derive(Clone,Debug,PartialEq)]
+pub enum HeaderField {
+    Path(Path),
+    Interface(String),
+    Member(String),
+    ErrorName(String),
+    ReplySerial(u32),
+    Destination(String),
+    Sender(String),
+    Signature(Signature),
+    UnixFds(u32),
+}
+
+impl HeaderField {
+    /// Returns the field code used on the wire
+    pub fn code(&self) -> u8 {
+        match self {
+            &HeaderField::Path(_) => 1,
+            &HeaderField::Interface(_) => 2,
+            &HeaderField::Member(_) => 3,
+            &HeaderField::ErrorName(_) => 4,
+            &HeaderField::ReplySerial(_) => 5,
+            &HeaderField::Destination(_) => 6,
+            &HeaderField::Sender(_) => 7,
+            &HeaderField::Signature(_) => 8,
+            &HeaderField::UnixFds(_) => 9,
+        }
+    }
+
+    /// Checks that the contents of the field are valid for its type
+    pub fn validate(&self) -> Result<(),MessageError> {
+        let valid = match self {
+            &HeaderField::Path(ref x) => x.validate().is_ok(),
+            &HeaderField::Interface(ref x) => InterfaceName::new(x).is_ok(),
+            &HeaderField::Member(ref x) => MemberName::new(x).is_ok(),
+            &HeaderField::ErrorName(ref x) => ErrorName::new(x).is_ok(),
+            &HeaderField::Destination(ref x) => BusName::new(x).is_ok(),
+            &HeaderField::Sender(ref x) => BusName::new(x).is_ok(),
+            &HeaderField::Signature(ref x) => x.validate().is_ok(),
+            &HeaderField::ReplySerial(_) | &HeaderField::UnixFds(_) => true,
+        };
+        if !valid {
+            return Err(MessageError::BadHeaderField(self.code()));
+        }
+        Ok(())
+    }
+
+    fn to_value(&self) -> Value {
+        let val = match self {
+            &HeaderField::Path(ref x) => BasicValue::ObjectPath(x.clone()),
+            &HeaderField::Interface(ref x) => BasicValue::String(x.clone()),
+            &HeaderField::Member(ref x) => BasicValue::String(x.clone()),
+            &HeaderField::ErrorName(ref x) => BasicValue::String(x.clone()),
+            &HeaderField::ReplySerial(x) => BasicValue::Uint32(x),
+            &HeaderField::Destination(ref x) => BasicValue::String(x.clone()),
+            &HeaderField::Sender(ref x) => BasicValue::String(x.clone()),
+            &HeaderField::Signature(ref x) => BasicValue::Signature(x.clone()),
+            &HeaderField::UnixFds(x) => BasicValue::Uint32(x),
+        };
+        let sig = val.get_signature().to_string();
+        Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::Byte(self.code())),
+                Value::Variant(Variant::new(Value::BasicValue(val), &sig)),
+            ],
+            signature: Signature("(yv)".to_string())
+        })
+    }
+
+    /// Converts a header field code and value into a HeaderField.  Returns Ok(None) for unknown
+    /// codes, which the specification requires to be ignored.
+    fn from_value(code: u8, v: Value) -> Result<Option<HeaderField>,MessageError> {
+        let field = match (code, v) {
+            (1, Value::BasicValue(BasicValue::ObjectPath(x))) => HeaderField::Path(x),
+            (2, Value::BasicValue(BasicValue::String(x))) => HeaderField::Interface(x),
+            (3, Value::BasicValue(BasicValue::String(x))) => HeaderField::Member(x),
+            (4, Value::BasicValue(BasicValue::String(x))) => HeaderField::ErrorName(x),
+            (5, Value::BasicValue(BasicValue::Uint32(x))) => HeaderField::ReplySerial(x),
+            (6, Value::BasicValue(BasicValue::String(x))) => HeaderField::Destination(x),
+            (7, Value::BasicValue(BasicValue::String(x))) => HeaderField::Sender(x),
+            (8, Value::BasicValue(BasicValue::Signature(x))) => HeaderField::Signature(x),
+            (9, Value::BasicValue(BasicValue::Uint32(x))) => HeaderField::UnixFds(x),
+            (1..=9, _) => return Err(MessageError::BadHeaderField(code)),
+            _ => return Ok(None)
+        };
+        Ok(Some(field))
+    }
+}
+
+#[derive(Debug,PartialEq)]
+pub enum MessageError {
+    Wire(WireError),
+    BadEndianness,
+    BadMessageType,
+    BadVersion,
+    BadHeader,
+    BadHeaderField(u8),
+    MissingHeaderField(u8),
+    SignatureMismatch,
+    TooLong,
+}
+
+impl From<WireError> for MessageError {
+    fn from(e: WireError) -> MessageError {
+        MessageError::Wire(e)
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Message {
+    pub endianness: Endianness,
+    pub message_type: MessageType,
+    pub flags: u8,
+    pub version: u8,
+    pub serial: u32,
+    /// Header fields.  A Signature field is added automatically when marshalling a message with a
+    /// non-empty body.
+    pub fields: Vec<HeaderField>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    /// Create a new message with no header fields and an empty body
+    pub fn new(message_type: MessageType, serial: u32) -> Message {
+        Message {
+            endianness: Endianness::native(),
+            message_type: message_type,
+            flags: 0,
+            version: PROTOCOL_VERSION,
+            serial: serial,
+            fields: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Create a method call message.  destination and interface are optional in a method call.
+    pub fn new_method_call(serial: u32, destination: Option<BusName>, path: Path,
+                           interface: Option<InterfaceName>, member: MemberName) -> Message {
+        let mut msg = Message::new(MessageType::MethodCall, serial);
+        msg.fields.push(HeaderField::Path(path));
+        if let Some(x) = interface {
+            msg.fields.push(HeaderField::Interface(x.into()));
+        }
+        msg.fields.push(HeaderField::Member(member.into()));
+        if let Some(x) = destination {
+            msg.fields.push(HeaderField::Destination(x.into()));
+        }
+        msg
+    }
+
+    /// Create a reply to the method call with serial reply_serial
+    pub fn new_method_return(serial: u32, reply_serial: u32) -> Message {
+        let mut msg = Message::new(MessageType::MethodReturn, serial);
+        msg.fields.push(HeaderField::ReplySerial(reply_serial));
+        msg
+    }
+
+    /// Create an error reply to the method call with serial reply_serial
+    pub fn new_error(serial: u32, reply_serial: u32, error_name: ErrorName) -> Message {
+        let mut msg = Message::new(MessageType::Error, serial);
+        msg.fields.push(HeaderField::ErrorName(error_name.into()));
+        msg.fields.push(HeaderField::ReplySerial(reply_serial));
+        msg
+    }
+
+    /// Create a signal message
+    pub fn new_signal(serial: u32, path: Path, interface: InterfaceName, member: MemberName) -> Message {
+        let mut msg = Message::new(MessageType::Signal, serial);
+        msg.fields.push(HeaderField::Path(path));
+        msg.fields.push(HeaderField::Interface(interface.into()));
+        msg.fields.push(HeaderField::Member(member.into()));
+        msg
+    }
+
+    fn get_field(&self, code: u8) -> Option<&HeaderField> {
+        self.fields.iter().find(|x| x.code() == code)
+    }
+
+    fn get_str_field(&self, code: u8) -> Option<&str> {
+        match self.get_field(code) {
+            Some(&HeaderField::Interface(ref x)) |
+            Some(&HeaderField::Member(ref x)) |
+            Some(&HeaderField::ErrorName(ref x)) |
+            Some(&HeaderField::Destination(ref x)) |
+            Some(&HeaderField::Sender(ref x)) => Some(x),
+            _ => None
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self.get_field(1) {
+            Some(&HeaderField::Path(ref x)) => Some(x),
+            _ => None
+        }
+    }
+
+    pub fn interface(&self) -> Option<&str> {
+        self.get_str_field(2)
+    }
+
+    pub fn member(&self) -> Option<&str> {
+        self.get_str_field(3)
+    }
+
+    pub fn error_name(&self) -> Option<&str> {
+        self.get_str_field(4)
+    }
+
+    pub fn reply_serial(&self) -> Option<u32> {
+        match self.get_field(5) {
+            Some(&HeaderField::ReplySerial(x)) => Some(x),
+            _ => None
+        }
+    }
+
+    pub fn destination(&self) -> Option<&str> {
+        self.get_str_field(6)
+    }
+
+    pub fn sender(&self) -> Option<&str> {
+        self.get_str_field(7)
+    }
+
+    pub fn unix_fds(&self) -> Option<u32> {
+        match self.get_field(9) {
+            Some(&HeaderField::UnixFds(x)) => Some(x),
+            _ => None
+        }
+    }
+
+    /// Returns the signature of the body, which is the concatenation of the signatures of the
+    /// body values
+    pub fn body_signature(&self) -> Signature {
+        let mut sig = String::new();
+        for v in self.body.iter() {
+            sig.push_str(v.get_signature());
+        }
+        Signature(sig)
+    }
+
+    /// Checks that the header fields required by the message type are present, and that the
+    /// names, path and signature in the header are valid
+    fn check_fields(&self) -> Result<(),MessageError> {
+        for field in self.fields.iter() {
+            try!(field.validate());
+        }
+
+        let required : &[u8] = match self.message_type {
+            MessageType::MethodCall => &[1, 3],
+            MessageType::MethodReturn => &[5],
+            MessageType::Error => &[4, 5],
+            MessageType::Signal => &[1, 2, 3],
+        };
+        for code in required {
+            if self.get_field(*code).is_none() {
+                return Err(MessageError::MissingHeaderField(*code));
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the message to the wire format
+    pub fn marshal(&self) -> Result<Vec<u8>,MessageError> {
+        try!(self.check_fields());
+
+        // The body starts on an 8-byte boundary, so it can be marshalled separately
+        let mut body = WireEncoder::new(self.endianness);
+        for v in self.body.iter() {
+            try!(body.write_value(v));
+        }
+        let body = body.into_bytes();
+
+        let body_sig = self.body_signature();
+        let mut fields = Vec::new();
+        for field in self.fields.iter() {
+            if let &HeaderField::Signature(ref x) = field {
+                if *x != body_sig {
+                    return Err(MessageError::SignatureMismatch);
+                }
+                continue;
+            }
+            fields.push(field.to_value());
+        }
+        if !body_sig.0.is_empty() {
+            fields.push(HeaderField::Signature(body_sig).to_value());
+        }
+
+        let header = Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::Byte(self.endianness.to_flag())),
+                Value::BasicValue(BasicValue::Byte(self.message_type.to_byte())),
+                Value::BasicValue(BasicValue::Byte(self.flags)),
+                Value::BasicValue(BasicValue::Byte(self.version)),
+                Value::BasicValue(BasicValue::Uint32(body.len() as u32)),
+                Value::BasicValue(BasicValue::Uint32(self.serial)),
+                Value::Array(Array::new_with_sig(fields, "a(yv)".to_string())),
+            ],
+            signature: Signature(HEADER_SIGNATURE.to_string())
+        });
+        let mut buf = try!(WireEncoder::encode(&header, self.endianness));
+        while !buf.len().is_multiple_of(8) {
+            buf.push(0);
+        }
+        buf.extend_from_slice(&body);
+
+        if buf.len() > MAX_MESSAGE_LEN {
+            return Err(MessageError::TooLong);
+        }
+        Ok(buf)
+    }
+
+    /// Parses a message from the start of buf.  Returns the message and the number of bytes it
+    /// occupied, since buf may hold further messages after the first.
+    pub fn unmarshal(buf: &[u8]) -> Result<(Message,usize),MessageError> {
+        let endianness = match buf.first().and_then(|x| Endianness::from_flag(*x)) {
+            Some(x) => x,
+            None => return Err(MessageError::BadEndianness)
+        };
+
+        let mut decoder = WireDecoder::new(buf, 0, endianness);
+        let mut header = match try!(decoder.read_value(HEADER_SIGNATURE)) {
+            Value::Struct(x) => x.objects.into_iter(),
+            _ => return Err(MessageError::BadHeader)
+        };
+        let mut next_byte = || match header.next() {
+            Some(Value::BasicValue(BasicValue::Byte(x))) => Ok(x),
+            _ => Err(MessageError::BadHeader)
+        };
+        try!(next_byte());
+        let message_type = match MessageType::from_byte(try!(next_byte())) {
+            Some(x) => x,
+            None => return Err(MessageError::BadMessageType)
+        };
+        let flags = try!(next_byte());
+        let version = try!(next_byte());
+        if version != PROTOCOL_VERSION {
+            return Err(MessageError::BadVersion);
+        }
+        let (body_len, serial, raw_fields) = match (header.next(), header.next(), header.next()) {
+            (Some(Value::BasicValue(BasicValue::Uint32(len))),
+             Some(Value::BasicValue(BasicValue::Uint32(serial))),
+             Some(Value::Array(fields))) => (len as usize, serial, fields.objects),
+            _ => return Err(MessageError::BadHeader)
+        };
+
+        let mut fields = Vec::new();
+        for raw_field in raw_fields {
+            let (code, value) = match raw_field {
+                Value::Struct(mut x) => match (x.objects.pop(), x.objects.pop()) {
+                    (Some(Value::Variant(v)), Some(Value::BasicValue(BasicValue::Byte(code)))) => (code, *v.object),
+                    _ => return Err(MessageError::BadHeader)
+                },
+                _ => return Err(MessageError::BadHeader)
+            };
+            if let Some(field) = try!(HeaderField::from_value(code, value)) {
+                fields.push(field);
+            }
+        }
+
+        let body_start = decoder.position().div_ceil(8) * 8;
+        if buf.len() < body_start || buf.len() - body_start < body_len {
+            return Err(MessageError::Wire(WireError::UnexpectedEof));
+        }
+        if buf[decoder.position()..body_start].iter().any(|x| *x != 0) {
+            return Err(MessageError::Wire(WireError::NonZeroPadding));
+        }
+        if body_start + body_len > MAX_MESSAGE_LEN {
+            return Err(MessageError::TooLong);
+        }
+
+        let mut msg = Message {
+            endianness: endianness,
+            message_type: message_type,
+            flags: flags,
+            version: version,
+            serial: serial,
+            fields: fields,
+            body: Vec::new(),
+        };
+        try!(msg.check_fields());
+
+        let body_sig = match msg.get_field(8) {
+            Some(&HeaderField::Signature(ref x)) => x.0.clone(),
+            _ => String::new()
+        };
+        if signature::validate(&body_sig).is_err() {
+            return Err(MessageError::BadHeaderField(8));
+        }
+        let body_types = match SignatureType::parse_list(&body_sig) {
+            Ok(x) => x,
+            Err(_) => return Err(MessageError::BadHeaderField(8))
+        };
+        let body_buf = &buf[body_start..body_start+body_len];
+        let mut body_decoder = WireDecoder::new(body_buf, 0, endianness);
+        for t in body_types.iter() {
+            msg.body.push(try!(body_decoder.read_type(t)));
+        }
+        if body_decoder.position() != body_len {
+            return Err(MessageError::SignatureMismatch);
+        }
+
+        Ok((msg, body_start + body_len))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use types::{Value,BasicValue,Path,Signature};
+    use wire::Endianness;
+    use names::{BusName,InterfaceName,MemberName,ErrorName};
+    use message::*;
+
+    #[test]
+    fn test_method_call () {
+        let mut msg = Message::new_method_call(1, Some(BusName::new("org.freedesktop.DBus").unwrap()),
+                                               Path("/org/freedesktop/DBus".to_string()),
+                                               Some(InterfaceName::new("org.freedesktop.DBus").unwrap()),
+                                               MemberName::new("Hello").unwrap());
+        msg.endianness = Endianness::Little;
+        let bytes = msg.marshal().unwrap();
+        assert_eq!(&bytes[..16], &[b'l', 1, 0, 1, 0,0,0,0, 1,0,0,0, 0x6d,0,0,0]);
+        assert_eq!(bytes.len(), 128);
+
+        let (msg2, len) = Message::unmarshal(&bytes).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(msg2, msg);
+        assert_eq!(msg2.member(), Some("Hello"));
+        assert_eq!(msg2.path(), Some(&Path("/org/freedesktop/DBus".to_string())));
+        assert_eq!(msg2.sender(), None);
+    }
+
+    #[test]
+    fn test_body () {
+        for &endian in &[Endianness::Little, Endianness::Big] {
+            let mut msg = Message::new_signal(7, Path("/a".to_string()), InterfaceName::new("a.b").unwrap(),
+                                              MemberName::new("C").unwrap());
+            msg.endianness = endian;
+            msg.body.push(Value::BasicValue(BasicValue::String("foo".to_string())));
+            msg.body.push(Value::BasicValue(BasicValue::Uint64(12)));
+            let mut bytes = msg.marshal().unwrap();
+            bytes.extend_from_slice(&[b'l', 4]);
+
+            let (msg2, len) = Message::unmarshal(&bytes).unwrap();
+            assert_eq!(len, bytes.len() - 2);
+            assert_eq!(msg2.body, msg.body);
+            assert_eq!(msg2.fields.last(), Some(&HeaderField::Signature(Signature("st".to_string()))));
+        }
+    }
+
+    #[test]
+    fn test_missing_field () {
+        let msg = Message::new(MessageType::Signal, 1);
+        assert_eq!(msg.marshal(), Err(MessageError::MissingHeaderField(1)));
+
+        let mut msg = Message::new_error(2, 1, ErrorName::new("org.example.Error").unwrap());
+        msg.fields.push(HeaderField::Signature(Signature("s".to_string())));
+        assert_eq!(msg.marshal(), Err(MessageError::SignatureMismatch));
+    }
+
+    #[test]
+    fn test_bad_field () {
+        let mut msg = Message::new_method_return(2, 1);
+        msg.fields.push(HeaderField::Destination("not a name".to_string()));
+        assert_eq!(msg.marshal(), Err(MessageError::BadHeaderField(6)));
+
+        let mut msg = Message::new_method_return(2, 1);
+        msg.fields.push(HeaderField::Path(Path("/trailing/".to_string())));
+        assert_eq!(msg.marshal(), Err(MessageError::BadHeaderField(1)));
+    }
+
+    #[test]
+    fn test_bad_header () {
+        assert_eq!(Message::unmarshal(&[b'x', 1]), Err(MessageError::BadEndianness));
+
+        let mut msg = Message::new_method_return(2, 1);
+        msg.endianness = Endianness::Little;
+        let mut bytes = msg.marshal().unwrap();
+        bytes[3] = 2;
+        assert_eq!(Message::unmarshal(&bytes), Err(MessageError::BadVersion));
+    }
+}
@@ -0,0 +1,282 @@
+//! Contains validated types for the names used in D-Bus messages: bus names, interface names,
+//! member names and error names.  Each type can only be constructed from a string which follows
+//! the rules in the D-Bus specification.
+#![allow(deprecated, clippy::match_ref_pats, clippy::needless_borrowed_reference)]
+use std::fmt;
+
+use types::{Value,BasicValue};
+
+/// Maximum length of any name, as set by the D-Bus specification
+pub const MAX_NAME_LEN: usize = 255;
+
+#[derive(Debug,PartialEq)]
+pub enum NameError {
+    Empty,
+    TooLong,
+    TooFewElements,
+    EmptyElement,
+    LeadingDigit,
+    InvalidCharacter(char),
+    NotUnique,
+    NotWellKnown,
+}
+
+/// Checks a name made up of elements separated by '.'.  allow_hyphen and allow_leading_digit
+/// select between the rules for the different kinds of name.
+fn check_dotted (name: &str, allow_hyphen: bool, allow_leading_digit: bool) -> Result<(),NameError> {
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(NameError::TooLong);
+    }
+    let mut count = 0;
+    for elem in name.split('.') {
+        try!(check_element(elem, allow_hyphen, allow_leading_digit));
+        count += 1;
+    }
+    if count < 2 {
+        return Err(NameError::TooFewElements);
+    }
+    Ok(())
+}
+
+fn check_element (elem: &str, allow_hyphen: bool, allow_leading_digit: bool) -> Result<(),NameError> {
+    let first = match elem.chars().next() {
+        Some(x) => x,
+        None => return Err(NameError::EmptyElement)
+    };
+    if !allow_leading_digit && first.is_ascii_digit() {
+        return Err(NameError::LeadingDigit);
+    }
+    for c in elem.chars() {
+        if !c.is_ascii_alphanumeric() && c != '_' && !(allow_hyphen && c == '-') {
+            return Err(NameError::InvalidCharacter(c));
+        }
+    }
+    Ok(())
+}
+
+macro_rules! name_type {
+    ($name:ident) => (
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(x: $name) -> String {
+                x.0
+            }
+        }
+
+        impl From<$name> for BasicValue {
+            fn from(x: $name) -> BasicValue {
+                BasicValue::String(x.0)
+            }
+        }
+
+        impl From<$name> for Value {
+            fn from(x: $name) -> Value {
+                Value::BasicValue(BasicValue::String(x.0))
+            }
+        }
+    )
+}
+
+/// A unique connection name, assigned by the bus, such as ":1.42"
+#[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
+pub struct UniqueName(String);
+
+impl UniqueName {
+    pub fn new(name: &str) -> Result<UniqueName,NameError> {
+        if !name.starts_with(':') {
+            return Err(NameError::NotUnique);
+        }
+        try!(check_dotted(&name[1..], true, true));
+        if name.len() > MAX_NAME_LEN {
+            return Err(NameError::TooLong);
+        }
+        Ok(UniqueName(name.to_string()))
+    }
+}
+
+name_type!(UniqueName);
+
+/// A well-known bus name, such as "org.freedesktop.DBus"
+#[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
+pub struct WellKnownName(String);
+
+impl WellKnownName {
+    pub fn new(name: &str) -> Result<WellKnownName,NameError> {
+        if name.starts_with(':') {
+            return Err(NameError::NotWellKnown);
+        }
+        try!(check_dotted(name, true, false));
+        Ok(WellKnownName(name.to_string()))
+    }
+}
+
+name_type!(WellKnownName);
+
+/// Either kind of bus name
+#[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
+pub enum BusName {
+    Unique(UniqueName),
+    WellKnown(WellKnownName),
+}
+
+impl BusName {
+    pub fn new(name: &str) -> Result<BusName,NameError> {
+        if name.starts_with(':') {
+            Ok(BusName::Unique(try!(UniqueName::new(name))))
+        } else {
+            Ok(BusName::WellKnown(try!(WellKnownName::new(name))))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            &BusName::Unique(ref x) => x.as_str(),
+            &BusName::WellKnown(ref x) => x.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for BusName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<BusName> for String {
+    fn from(x: BusName) -> String {
+        match x {
+            BusName::Unique(x) => x.0,
+            BusName::WellKnown(x) => x.0,
+        }
+    }
+}
+
+impl From<BusName> for BasicValue {
+    fn from(x: BusName) -> BasicValue {
+        BasicValue::String(String::from(x))
+    }
+}
+
+impl From<BusName> for Value {
+    fn from(x: BusName) -> Value {
+        Value::BasicValue(BasicValue::from(x))
+    }
+}
+
+/// An interface name, such as "org.freedesktop.DBus.Properties"
+#[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
+pub struct InterfaceName(String);
+
+impl InterfaceName {
+    pub fn new(name: &str) -> Result<InterfaceName,NameError> {
+        try!(check_dotted(name, false, false));
+        Ok(InterfaceName(name.to_string()))
+    }
+}
+
+name_type!(InterfaceName);
+
+/// A method or signal name, such as "GetAll"
+#[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
+pub struct MemberName(String);
+
+impl MemberName {
+    pub fn new(name: &str) -> Result<MemberName,NameError> {
+        if name.len() > MAX_NAME_LEN {
+            return Err(NameError::TooLong);
+        }
+        match check_element(name, false, false) {
+            Err(NameError::EmptyElement) => return Err(NameError::Empty),
+            Err(e) => return Err(e),
+            Ok(_) => ()
+        }
+        Ok(MemberName(name.to_string()))
+    }
+}
+
+name_type!(MemberName);
+
+/// An error name, such as "org.freedesktop.DBus.Error.Failed".  Error names follow the same rules
+/// as interface names.
+#[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
+pub struct ErrorName(String);
+
+impl ErrorName {
+    pub fn new(name: &str) -> Result<ErrorName,NameError> {
+        try!(check_dotted(name, false, false));
+        Ok(ErrorName(name.to_string()))
+    }
+}
+
+name_type!(ErrorName);
+
+#[cfg(test)]
+mod test {
+    use types::{Value,BasicValue};
+    use names::*;
+
+    #[test]
+    fn test_bus_names () {
+        assert!(UniqueName::new(":1.42").is_ok());
+        assert!(UniqueName::new(":1.2-3.x_y").is_ok());
+        assert_eq!(UniqueName::new("1.42"), Err(NameError::NotUnique));
+        assert_eq!(UniqueName::new(":1"), Err(NameError::TooFewElements));
+        assert_eq!(UniqueName::new(":1..2"), Err(NameError::EmptyElement));
+
+        assert!(WellKnownName::new("org.freedesktop.DBus").is_ok());
+        assert!(WellKnownName::new("com.example.my-service").is_ok());
+        assert_eq!(WellKnownName::new(":1.42"), Err(NameError::NotWellKnown));
+        assert_eq!(WellKnownName::new("org.7zip"), Err(NameError::LeadingDigit));
+        assert_eq!(WellKnownName::new(".org.example"), Err(NameError::EmptyElement));
+        assert_eq!(WellKnownName::new("org.example."), Err(NameError::EmptyElement));
+        assert_eq!(WellKnownName::new(""), Err(NameError::Empty));
+
+        let long = "a.".repeat(127) + "ab";
+        assert_eq!(WellKnownName::new(&long), Err(NameError::TooLong));
+
+        match BusName::new(":1.5").unwrap() {
+            BusName::Unique(x) => assert_eq!(x.as_str(), ":1.5"),
+            _ => panic!("Expected a unique name")
+        }
+    }
+
+    #[test]
+    fn test_interface_and_error_names () {
+        assert!(InterfaceName::new("org.freedesktop.DBus.Properties").is_ok());
+        assert_eq!(InterfaceName::new("org"), Err(NameError::TooFewElements));
+        assert_eq!(InterfaceName::new("org.my-iface"), Err(NameError::InvalidCharacter('-')));
+        assert!(ErrorName::new("org.freedesktop.DBus.Error.Failed").is_ok());
+        assert_eq!(ErrorName::new("Failed"), Err(NameError::TooFewElements));
+    }
+
+    #[test]
+    fn test_member_names () {
+        assert!(MemberName::new("GetAll").is_ok());
+        assert!(MemberName::new("_private2").is_ok());
+        assert_eq!(MemberName::new(""), Err(NameError::Empty));
+        assert_eq!(MemberName::new("2Get"), Err(NameError::LeadingDigit));
+        assert_eq!(MemberName::new("a.b"), Err(NameError::InvalidCharacter('.')));
+    }
+
+    #[test]
+    fn test_conversion () {
+        let name = MemberName::new("Hello").unwrap();
+        assert_eq!(Value::from(name.clone()), Value::BasicValue(BasicValue::String("Hello".to_string())));
+        assert_eq!(name.to_string(), "Hello");
+        assert_eq!(String::from(BusName::new("a.b").unwrap()), "a.b");
+    }
+}
@@ -0,0 +1,429 @@
+//! Implements the serde::Serializer trait, producing a Value.  The mapping is the same as for
+//! DBusEncoder: structs and tuples become Struct, sequences become Array and maps become
+//! Dictionary.
+#![allow(deprecated, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
+use std::error;
+use std::fmt;
+
+use serde::ser::{self,Serialize};
+
+use signature::SignatureType;
+use types::{Value,BasicValue,Struct,Signature,DictEntry,Dictionary,Array,Path};
+
+#[derive(Debug,PartialEq)]
+pub enum SerializeError {
+    BadKeyType,
+    Unsupported,
+    EmptyArray,
+    EmptyMap,
+    /// The elements of a sequence, or the keys or values of a map, do not all have the same
+    /// signature
+    TypeMismatch,
+    InvalidObjectPath,
+    InvalidSignature,
+    Custom(String),
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SerializeError::BadKeyType => f.write_str("dictionary keys must be basic types"),
+            &SerializeError::Unsupported => f.write_str("type cannot be represented in D-Bus"),
+            &SerializeError::EmptyArray => f.write_str("cannot determine the signature of an empty array"),
+            &SerializeError::EmptyMap => f.write_str("cannot determine the signature of an empty map"),
+            &SerializeError::TypeMismatch => f.write_str("sequence or map elements do not all have the same signature"),
+            &SerializeError::InvalidObjectPath => f.write_str("invalid object path"),
+            &SerializeError::InvalidSignature => f.write_str("invalid signature"),
+            &SerializeError::Custom(ref x) => f.write_str(x),
+        }
+    }
+}
+
+impl error::Error for SerializeError {
+}
+
+impl ser::Error for SerializeError {
+    fn custom<T: fmt::Display>(msg: T) -> SerializeError {
+        SerializeError::Custom(msg.to_string())
+    }
+}
+
+/// Converts any Serialize type to a Value
+pub fn to_value<T: Serialize + ?Sized>(x: &T) -> Result<Value,SerializeError> {
+    x.serialize(ValueSerializer)
+}
+
+pub struct ValueSerializer;
+
+/// The newtype struct names under which Path and Signature serialize themselves.  ValueSerializer
+/// recognises them and produces 'o' and 'g' values; other serializers just see a string.
+pub(crate) const PATH_STRUCT_NAME: &str = "dbus_serialize::types::Path";
+pub(crate) const SIGNATURE_STRUCT_NAME: &str = "dbus_serialize::types::Signature";
+
+fn basic (v: BasicValue) -> Result<Value,SerializeError> {
+    Ok(Value::BasicValue(v))
+}
+
+fn make_struct (objects: Vec<Value>) -> Result<Value,SerializeError> {
+    let mut fields = Vec::new();
+    for v in objects.iter() {
+        fields.push(try!(v.get_type().map_err(|_| SerializeError::Unsupported)));
+    }
+    Ok(Value::Struct(Struct {
+        objects: objects,
+        signature: Signature(SignatureType::Struct(fields).to_string())
+    }))
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = StructSerializer;
+    type SerializeTupleStruct = StructSerializer;
+    type SerializeTupleVariant = ser::Impossible<Value,SerializeError>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = ser::Impossible<Value,SerializeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value,SerializeError> {
+        basic(BasicValue::Boolean(v))
+    }
+    fn serialize_i8(self, _v: i8) -> Result<Value,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_i16(self, v: i16) -> Result<Value,SerializeError> {
+        basic(BasicValue::Int16(v))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Value,SerializeError> {
+        basic(BasicValue::Int32(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Value,SerializeError> {
+        basic(BasicValue::Int64(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Value,SerializeError> {
+        basic(BasicValue::Byte(v))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Value,SerializeError> {
+        basic(BasicValue::Uint16(v))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Value,SerializeError> {
+        basic(BasicValue::Uint32(v))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Value,SerializeError> {
+        basic(BasicValue::Uint64(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Value,SerializeError> {
+        basic(BasicValue::Double(v as f64))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Value,SerializeError> {
+        basic(BasicValue::Double(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Value,SerializeError> {
+        basic(BasicValue::Byte(v as u8))
+    }
+    fn serialize_str(self, v: &str) -> Result<Value,SerializeError> {
+        basic(BasicValue::String(v.to_string()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value,SerializeError> {
+        let objects = v.iter().map(|x| Value::BasicValue(BasicValue::Byte(*x))).collect();
+        Ok(Value::Array(Array::new_with_sig(objects, "ay".to_string())))
+    }
+    fn serialize_none(self) -> Result<Value,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<Value,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_unit(self) -> Result<Value,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str)
+                              -> Result<Value,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T)
+                                                       -> Result<Value,SerializeError> {
+        let v = try!(value.serialize(self));
+        match (name, v) {
+            (PATH_STRUCT_NAME, Value::BasicValue(BasicValue::String(x))) => {
+                let path = Path(x);
+                if path.validate().is_err() {
+                    return Err(SerializeError::InvalidObjectPath);
+                }
+                basic(BasicValue::ObjectPath(path))
+            },
+            (SIGNATURE_STRUCT_NAME, Value::BasicValue(BasicValue::String(x))) => {
+                let sig = Signature(x);
+                if sig.validate().is_err() {
+                    return Err(SerializeError::InvalidSignature);
+                }
+                basic(BasicValue::Signature(sig))
+            },
+            (_, v) => Ok(v)
+        }
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _variant_index: u32,
+                                                        _variant: &'static str, _value: &T)
+                                                        -> Result<Value,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer,SerializeError> {
+        Ok(SeqSerializer {
+            objects: Vec::with_capacity(len.unwrap_or(0))
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<StructSerializer,SerializeError> {
+        Ok(StructSerializer {
+            objects: Vec::with_capacity(len)
+        })
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<StructSerializer,SerializeError> {
+        self.serialize_tuple(len)
+    }
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str,
+                               _len: usize) -> Result<Self::SerializeTupleVariant,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer,SerializeError> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None
+        })
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<StructSerializer,SerializeError> {
+        self.serialize_tuple(len)
+    }
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str,
+                                _len: usize) -> Result<Self::SerializeStructVariant,SerializeError> {
+        Err(SerializeError::Unsupported)
+    }
+}
+
+pub struct SeqSerializer {
+    objects: Vec<Value>
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(),SerializeError> {
+        self.objects.push(try!(value.serialize(ValueSerializer)));
+        Ok(())
+    }
+    fn end(self) -> Result<Value,SerializeError> {
+        if self.objects.is_empty() {
+            return Err(SerializeError::EmptyArray);
+        }
+        let sig = self.objects[0].get_signature();
+        if self.objects.iter().any(|x| x.get_signature() != sig) {
+            return Err(SerializeError::TypeMismatch);
+        }
+        Ok(Value::Array(Array::new(self.objects)))
+    }
+}
+
+/// Serializes structs and tuples
+pub struct StructSerializer {
+    objects: Vec<Value>
+}
+
+impl ser::SerializeTuple for StructSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(),SerializeError> {
+        self.objects.push(try!(value.serialize(ValueSerializer)));
+        Ok(())
+    }
+    fn end(self) -> Result<Value,SerializeError> {
+        make_struct(self.objects)
+    }
+}
+
+impl ser::SerializeTupleStruct for StructSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(),SerializeError> {
+        ser::SerializeTuple::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value,SerializeError> {
+        make_struct(self.objects)
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(),SerializeError> {
+        ser::SerializeTuple::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value,SerializeError> {
+        make_struct(self.objects)
+    }
+}
+
+pub struct MapSerializer {
+    entries: Vec<DictEntry>,
+    key: Option<BasicValue>
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(),SerializeError> {
+        self.key = match try!(key.serialize(ValueSerializer)) {
+            Value::BasicValue(x) => Some(x),
+            _ => return Err(SerializeError::BadKeyType)
+        };
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(),SerializeError> {
+        let key = self.key.take().unwrap();
+        self.entries.push(DictEntry {
+            key: key,
+            value: try!(value.serialize(ValueSerializer))
+        });
+        Ok(())
+    }
+    fn end(self) -> Result<Value,SerializeError> {
+        if self.entries.is_empty() {
+            return Err(SerializeError::EmptyMap);
+        }
+        let key_sig = self.entries[0].key.get_signature();
+        let value_sig = self.entries[0].value.get_signature();
+        if self.entries.iter().any(|e| e.key.get_signature() != key_sig || e.value.get_signature() != value_sig) {
+            return Err(SerializeError::TypeMismatch);
+        }
+        Ok(Value::Dictionary(Dictionary::new(self.entries)))
+    }
+}
+
+impl Serialize for Path {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok,S::Error> {
+        s.serialize_newtype_struct(PATH_STRUCT_NAME, &self.0)
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok,S::Error> {
+        s.serialize_newtype_struct(SIGNATURE_STRUCT_NAME, &self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use types::{Value,BasicValue,Struct,Signature,DictEntry,Dictionary,Array,Path};
+    use serializer::*;
+
+    #[derive(Serialize)]
+    struct TestStruct {
+        x: u32,
+        y: Vec<String>,
+        z: (i16, bool),
+    }
+
+    #[test]
+    fn test_basic () {
+        assert_eq!(to_value(&5u32), Ok(Value::BasicValue(BasicValue::Uint32(5))));
+        assert_eq!(to_value("foo"), Ok(Value::BasicValue(BasicValue::String("foo".to_string()))));
+        assert_eq!(to_value(&1.5f64), Ok(Value::BasicValue(BasicValue::Double(1.5))));
+        assert_eq!(to_value(&None::<u32>), Err(SerializeError::Unsupported));
+        assert_eq!(to_value(&()), Err(SerializeError::Unsupported));
+    }
+
+    #[test]
+    fn test_struct () {
+        let s = TestStruct { x: 1, y: vec!["a".to_string()], z: (-1, true) };
+        let v = to_value(&s).unwrap();
+        let expected = Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::Uint32(1)),
+                Value::Array(Array::new(vec![Value::BasicValue(BasicValue::String("a".to_string()))])),
+                Value::Struct(Struct {
+                    objects: vec![
+                        Value::BasicValue(BasicValue::Int16(-1)),
+                        Value::BasicValue(BasicValue::Boolean(true)),
+                    ],
+                    signature: Signature("(nb)".to_string())
+                }),
+            ],
+            signature: Signature("(uas(nb))".to_string())
+        });
+        assert_eq!(v, expected);
+    }
+
+    #[test]
+    fn test_map () {
+        let mut map = BTreeMap::new();
+        assert_eq!(to_value(&map), Err(SerializeError::EmptyMap));
+        map.insert(1u8, "x".to_string());
+        let expected = Value::Dictionary(Dictionary::new(vec![
+            DictEntry {
+                key: BasicValue::Byte(1),
+                value: Value::BasicValue(BasicValue::String("x".to_string()))
+            }
+        ]));
+        assert_eq!(to_value(&map), Ok(expected));
+
+        let mut map = BTreeMap::new();
+        map.insert((1u8, 2u8), 3u8);
+        assert_eq!(to_value(&map), Err(SerializeError::BadKeyType));
+        assert_eq!(to_value(&Vec::<u8>::new()), Err(SerializeError::EmptyArray));
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), Mixed::Int(1));
+        map.insert("b".to_string(), Mixed::Str("x".to_string()));
+        assert_eq!(to_value(&map), Err(SerializeError::TypeMismatch));
+    }
+
+    struct Bytes<'a>(&'a [u8]);
+
+    impl<'a> Serialize for Bytes<'a> {
+        fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok,S::Error> {
+            s.serialize_bytes(self.0)
+        }
+    }
+
+    #[test]
+    fn test_bytes () {
+        assert_eq!(to_value(&Bytes(&[1, 2])), Ok(Value::Array(Array::new_with_sig(vec![
+            Value::BasicValue(BasicValue::Byte(1)),
+            Value::BasicValue(BasicValue::Byte(2)),
+        ], "ay".to_string()))));
+        assert_eq!(to_value(&Bytes(&[])), Ok(Value::Array(Array::new_with_sig(vec![], "ay".to_string()))));
+    }
+
+    #[test]
+    fn test_path_and_signature () {
+        assert_eq!(to_value(&Path("/a".to_string())),
+                   Ok(Value::BasicValue(BasicValue::ObjectPath(Path("/a".to_string())))));
+        assert_eq!(to_value(&Signature("as".to_string())),
+                   Ok(Value::BasicValue(BasicValue::Signature(Signature("as".to_string())))));
+        assert_eq!(to_value(&Path("a".to_string())), Err(SerializeError::InvalidObjectPath));
+        assert_eq!(to_value(&Signature("a".to_string())), Err(SerializeError::InvalidSignature));
+    }
+
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Mixed {
+        Int(u32),
+        Str(String),
+    }
+
+    #[test]
+    fn test_mixed_seq () {
+        let v = vec![Mixed::Int(1), Mixed::Int(2)];
+        assert_eq!(to_value(&v).unwrap().get_signature(), "au");
+        let v = vec![Mixed::Int(1), Mixed::Str("x".to_string())];
+        assert_eq!(to_value(&v), Err(SerializeError::TypeMismatch));
+    }
+}
@@ -0,0 +1,592 @@
+//! Contains SignatureType, a parsed representation of a D-Bus type signature
+#![allow(deprecated, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
+use std::collections::{BTreeMap,HashMap,VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use types::{BasicValue,Path,Signature,Value,Variant};
+
+/// Maximum length of a signature, as set by the D-Bus specification
+pub const MAX_SIGNATURE_LEN: usize = 255;
+
+/// Maximum nesting depth of arrays within a signature
+pub const MAX_ARRAY_DEPTH: usize = 32;
+
+/// Maximum nesting depth of structs and dict entries within a signature
+pub const MAX_STRUCT_DEPTH: usize = 32;
+
+/// Maximum total nesting depth of containers accepted by the parser.  This is only a guard
+/// against stack exhaustion; the per-kind limits above are checked by validate.
+pub const MAX_DEPTH: usize = MAX_ARRAY_DEPTH + MAX_STRUCT_DEPTH;
+
+/// The types which may be used as dictionary keys
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum BasicType {
+    Byte,
+    Boolean,
+    Double,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Int64,
+    Uint64,
+    String,
+    ObjectPath,
+    Signature,
+    UnixFd,
+}
+
+impl BasicType {
+    /// Returns the BasicType for a type code, or None if it is not the code of a basic type
+    pub fn from_code(code: char) -> Option<BasicType> {
+        let t = match code {
+            'y' => BasicType::Byte,
+            'b' => BasicType::Boolean,
+            'd' => BasicType::Double,
+            'n' => BasicType::Int16,
+            'q' => BasicType::Uint16,
+            'i' => BasicType::Int32,
+            'u' => BasicType::Uint32,
+            'x' => BasicType::Int64,
+            't' => BasicType::Uint64,
+            's' => BasicType::String,
+            'o' => BasicType::ObjectPath,
+            'g' => BasicType::Signature,
+            'h' => BasicType::UnixFd,
+            _ => return None
+        };
+        Some(t)
+    }
+
+    /// Returns the type code
+    pub fn code(&self) -> char {
+        match self {
+            &BasicType::Byte => 'y',
+            &BasicType::Boolean => 'b',
+            &BasicType::Double => 'd',
+            &BasicType::Int16 => 'n',
+            &BasicType::Uint16 => 'q',
+            &BasicType::Int32 => 'i',
+            &BasicType::Uint32 => 'u',
+            &BasicType::Int64 => 'x',
+            &BasicType::Uint64 => 't',
+            &BasicType::String => 's',
+            &BasicType::ObjectPath => 'o',
+            &BasicType::Signature => 'g',
+            &BasicType::UnixFd => 'h',
+        }
+    }
+}
+
+/// A single complete type.  Dictionary entries are only allowed as array elements, so an array of
+/// dictionary entries is represented by the Dict node rather than by Array.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub enum SignatureType {
+    Basic(BasicType),
+    Array(Box<SignatureType>),
+    Dict(BasicType, Box<SignatureType>),
+    Struct(Vec<SignatureType>),
+    Variant,
+    /// GVariant's maybe type ('m').  This is not part of the D-Bus type system, so it is only
+    /// produced by SignatureType::parse_gvariant.
+    Maybe(Box<SignatureType>),
+}
+
+#[derive(Debug,PartialEq)]
+pub enum SignatureError {
+    Empty,
+    UnexpectedEnd,
+    UnknownTypeCode(char),
+    UnexpectedCharacter(char),
+    DictKeyNotBasic,
+    DictEntryOutsideArray,
+    BadDictEntry,
+    TrailingCharacters,
+    TooLong,
+    ArrayTooDeep,
+    StructTooDeep,
+    TooDeep,
+    EmptyStruct,
+    NotAnArray,
+    NotADictionary,
+}
+
+struct Parser<'a> {
+    sig: &'a [u8],
+    pos: usize,
+    depth: usize,
+    gvariant: bool
+}
+
+impl<'a> Parser<'a> {
+    fn next (&mut self) -> Result<u8,SignatureError> {
+        match self.sig.get(self.pos) {
+            Some(x) => {
+                self.pos += 1;
+                Ok(*x)
+            },
+            None => Err(SignatureError::UnexpectedEnd)
+        }
+    }
+
+    fn peek (&self) -> Option<u8> {
+        self.sig.get(self.pos).cloned()
+    }
+
+    fn parse_type (&mut self) -> Result<SignatureType,SignatureError> {
+        if self.depth > MAX_DEPTH {
+            return Err(SignatureError::TooDeep);
+        }
+        self.depth += 1;
+        let result = self.parse_single();
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_single (&mut self) -> Result<SignatureType,SignatureError> {
+        let t = match try!(self.next()) {
+            b'a' if self.peek() == Some(b'{') => {
+                self.pos += 1;
+                let key = match try!(self.parse_type()) {
+                    SignatureType::Basic(x) => x,
+                    _ => return Err(SignatureError::DictKeyNotBasic)
+                };
+                let value = try!(self.parse_type());
+                if try!(self.next()) != b'}' {
+                    return Err(SignatureError::BadDictEntry);
+                }
+                SignatureType::Dict(key, Box::new(value))
+            },
+            b'a' => SignatureType::Array(Box::new(try!(self.parse_type()))),
+            b'(' => {
+                let mut fields = Vec::new();
+                while self.peek() != Some(b')') {
+                    fields.push(try!(self.parse_type()));
+                }
+                self.pos += 1;
+                SignatureType::Struct(fields)
+            },
+            b'v' => SignatureType::Variant,
+            b'm' if self.gvariant => SignatureType::Maybe(Box::new(try!(self.parse_type()))),
+            b'{' => return Err(SignatureError::DictEntryOutsideArray),
+            c @ b')' | c @ b'}' => return Err(SignatureError::UnexpectedCharacter(c as char)),
+            c => match BasicType::from_code(c as char) {
+                Some(x) => SignatureType::Basic(x),
+                None => return Err(SignatureError::UnknownTypeCode(c as char))
+            }
+        };
+        Ok(t)
+    }
+}
+
+impl SignatureType {
+    /// Parses a signature consisting of exactly one complete type
+    pub fn parse(sig: &str) -> Result<SignatureType,SignatureError> {
+        SignatureType::parse_with(sig, false)
+    }
+
+    /// Like parse, but also accepts GVariant's maybe type ('m')
+    pub fn parse_gvariant(sig: &str) -> Result<SignatureType,SignatureError> {
+        SignatureType::parse_with(sig, true)
+    }
+
+    fn parse_with(sig: &str, gvariant: bool) -> Result<SignatureType,SignatureError> {
+        if sig.is_empty() {
+            return Err(SignatureError::Empty);
+        }
+        let mut parser = Parser { sig: sig.as_bytes(), pos: 0, depth: 0, gvariant: gvariant };
+        let t = try!(parser.parse_type());
+        if parser.pos != sig.len() {
+            return Err(SignatureError::TrailingCharacters);
+        }
+        Ok(t)
+    }
+
+    /// Parses a signature consisting of any number of complete types, such as the signature of a
+    /// message body
+    pub fn parse_list(sig: &str) -> Result<Vec<SignatureType>,SignatureError> {
+        let mut parser = Parser { sig: sig.as_bytes(), pos: 0, depth: 0, gvariant: false };
+        let mut types = Vec::new();
+        while parser.pos != sig.len() {
+            types.push(try!(parser.parse_type()));
+        }
+        Ok(types)
+    }
+
+    fn check_nesting(&self, arrays: usize, structs: usize) -> Result<(),SignatureError> {
+        match self {
+            &SignatureType::Basic(_) | &SignatureType::Variant => Ok(()),
+            &SignatureType::Array(ref x) => {
+                if arrays == MAX_ARRAY_DEPTH {
+                    return Err(SignatureError::ArrayTooDeep);
+                }
+                x.check_nesting(arrays + 1, structs)
+            },
+            &SignatureType::Dict(_, ref x) => {
+                if arrays == MAX_ARRAY_DEPTH {
+                    return Err(SignatureError::ArrayTooDeep);
+                }
+                if structs == MAX_STRUCT_DEPTH {
+                    return Err(SignatureError::StructTooDeep);
+                }
+                x.check_nesting(arrays + 1, structs + 1)
+            },
+            &SignatureType::Struct(ref fields) => {
+                if fields.is_empty() {
+                    return Err(SignatureError::EmptyStruct);
+                }
+                if structs == MAX_STRUCT_DEPTH {
+                    return Err(SignatureError::StructTooDeep);
+                }
+                for x in fields.iter() {
+                    try!(x.check_nesting(arrays, structs + 1));
+                }
+                Ok(())
+            },
+            &SignatureType::Maybe(ref x) => x.check_nesting(arrays, structs),
+        }
+    }
+
+    /// Checks the rules which the D-Bus specification places on a signature beyond its basic
+    /// syntax: the length limit, the nesting limits and the ban on empty structs
+    pub fn validate(&self) -> Result<(),SignatureError> {
+        if self.to_string().len() > MAX_SIGNATURE_LEN {
+            return Err(SignatureError::TooLong);
+        }
+        self.check_nesting(0, 0)
+    }
+
+    /// Returns true if the type is basic, meaning that it may be used as a dictionary key
+    pub fn is_basic(&self) -> bool {
+        matches!(self, &SignatureType::Basic(_))
+    }
+}
+
+impl fmt::Display for SignatureType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SignatureType::Basic(ref x) => write!(f, "{}", x.code()),
+            &SignatureType::Array(ref x) => write!(f, "a{}", x),
+            &SignatureType::Dict(ref k, ref v) => write!(f, "a{{{}{}}}", k.code(), v),
+            &SignatureType::Struct(ref fields) => {
+                try!(write!(f, "("));
+                for x in fields.iter() {
+                    try!(write!(f, "{}", x));
+                }
+                write!(f, ")")
+            },
+            &SignatureType::Variant => write!(f, "v"),
+            &SignatureType::Maybe(ref x) => write!(f, "m{}", x),
+        }
+    }
+}
+
+impl FromStr for SignatureType {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<SignatureType,SignatureError> {
+        SignatureType::parse(s)
+    }
+}
+
+/// Checks that sig, which may hold any number of complete types, is valid according to the D-Bus
+/// specification
+pub fn validate(sig: &str) -> Result<(),SignatureError> {
+    if sig.len() > MAX_SIGNATURE_LEN {
+        return Err(SignatureError::TooLong);
+    }
+    for t in try!(SignatureType::parse_list(sig)) {
+        try!(t.check_nesting(0, 0));
+    }
+    Ok(())
+}
+
+impl Signature {
+    /// Create a new signature, checking that it is valid.  The signature may hold any number of
+    /// complete types.
+    pub fn new(sig: &str) -> Result<Signature,SignatureError> {
+        try!(validate(sig));
+        Ok(Signature(sig.to_string()))
+    }
+
+    /// Checks that the signature is valid according to the D-Bus specification
+    pub fn validate(&self) -> Result<(),SignatureError> {
+        validate(&self.0)
+    }
+
+    /// Parses the signature, which must consist of exactly one complete type
+    pub fn parse(&self) -> Result<SignatureType,SignatureError> {
+        SignatureType::parse(&self.0)
+    }
+}
+
+impl Value {
+    /// Returns the parsed type of the Value.  This only fails if the Value holds an invalid
+    /// signature.  Since a Value may be a Maybe, GVariant's maybe type is accepted.
+    pub fn get_type(&self) -> Result<SignatureType,SignatureError> {
+        SignatureType::parse_gvariant(self.get_signature())
+    }
+}
+
+impl BasicValue {
+    /// Returns the type of the BasicValue
+    pub fn get_basic_type(&self) -> BasicType {
+        match self {
+            &BasicValue::Byte(_) => BasicType::Byte,
+            &BasicValue::Boolean(_) => BasicType::Boolean,
+            &BasicValue::Double(_) => BasicType::Double,
+            &BasicValue::Int16(_) => BasicType::Int16,
+            &BasicValue::Uint16(_) => BasicType::Uint16,
+            &BasicValue::Int32(_) => BasicType::Int32,
+            &BasicValue::Uint32(_) => BasicType::Uint32,
+            &BasicValue::Int64(_) => BasicType::Int64,
+            &BasicValue::Uint64(_) => BasicType::Uint64,
+            &BasicValue::String(_) => BasicType::String,
+            &BasicValue::ObjectPath(_) => BasicType::ObjectPath,
+            &BasicValue::Signature(_) => BasicType::Signature,
+        }
+    }
+}
+
+impl From<SignatureType> for Signature {
+    fn from(t: SignatureType) -> Signature {
+        Signature(t.to_string())
+    }
+}
+
+impl From<&SignatureType> for Signature {
+    fn from(t: &SignatureType) -> Signature {
+        Signature(t.to_string())
+    }
+}
+
+/// Rust types which always correspond to the same D-Bus type, whatever their value.  This allows
+/// the signature of an empty Vec or map to be known.
+pub trait DBusType {
+    /// Returns the D-Bus type of Self
+    fn signature() -> SignatureType;
+}
+
+/// Rust types which correspond to a basic D-Bus type, and so may be used as dictionary keys
+pub trait DBusBasicType: DBusType {
+    fn basic_type() -> BasicType;
+}
+
+macro_rules! basic_dbus_type {
+    ($t:ty, $basic:ident) => (
+        impl DBusType for $t {
+            fn signature() -> SignatureType {
+                SignatureType::Basic(BasicType::$basic)
+            }
+        }
+
+        impl DBusBasicType for $t {
+            fn basic_type() -> BasicType {
+                BasicType::$basic
+            }
+        }
+    )
+}
+
+basic_dbus_type!(u8, Byte);
+basic_dbus_type!(bool, Boolean);
+basic_dbus_type!(f64, Double);
+basic_dbus_type!(i16, Int16);
+basic_dbus_type!(u16, Uint16);
+basic_dbus_type!(i32, Int32);
+basic_dbus_type!(u32, Uint32);
+basic_dbus_type!(i64, Int64);
+basic_dbus_type!(u64, Uint64);
+basic_dbus_type!(String, String);
+basic_dbus_type!(str, String);
+basic_dbus_type!(Path, ObjectPath);
+basic_dbus_type!(Signature, Signature);
+
+impl<T: DBusType + ?Sized> DBusType for &T {
+    fn signature() -> SignatureType {
+        T::signature()
+    }
+}
+
+impl<T: DBusBasicType + ?Sized> DBusBasicType for &T {
+    fn basic_type() -> BasicType {
+        T::basic_type()
+    }
+}
+
+impl<T: DBusType + ?Sized> DBusType for Box<T> {
+    fn signature() -> SignatureType {
+        T::signature()
+    }
+}
+
+impl DBusType for Variant {
+    fn signature() -> SignatureType {
+        SignatureType::Variant
+    }
+}
+
+/// The type of an Option depends on the OptionEncoding used, so it is given as GVariant's maybe
+/// type.  DBusEncoder::encode_typed and DBusDecoder::decode_typed resolve it to the real type.
+impl<T: DBusType> DBusType for Option<T> {
+    fn signature() -> SignatureType {
+        SignatureType::Maybe(Box::new(T::signature()))
+    }
+}
+
+impl<T: DBusType> DBusType for Vec<T> {
+    fn signature() -> SignatureType {
+        SignatureType::Array(Box::new(T::signature()))
+    }
+}
+
+impl<T: DBusType> DBusType for VecDeque<T> {
+    fn signature() -> SignatureType {
+        SignatureType::Array(Box::new(T::signature()))
+    }
+}
+
+impl<T: DBusType> DBusType for [T] {
+    fn signature() -> SignatureType {
+        SignatureType::Array(Box::new(T::signature()))
+    }
+}
+
+impl<K: DBusBasicType + Eq + Hash, V: DBusType> DBusType for HashMap<K,V> {
+    fn signature() -> SignatureType {
+        SignatureType::Dict(K::basic_type(), Box::new(V::signature()))
+    }
+}
+
+impl<K: DBusBasicType + Ord, V: DBusType> DBusType for BTreeMap<K,V> {
+    fn signature() -> SignatureType {
+        SignatureType::Dict(K::basic_type(), Box::new(V::signature()))
+    }
+}
+
+macro_rules! tuple_dbus_type {
+    ($($name:ident)+) => (
+        impl<$($name: DBusType),+> DBusType for ($($name,)+) {
+            fn signature() -> SignatureType {
+                SignatureType::Struct(vec![$($name::signature()),+])
+            }
+        }
+    )
+}
+
+tuple_dbus_type!(A);
+tuple_dbus_type!(A B);
+tuple_dbus_type!(A B C);
+tuple_dbus_type!(A B C D);
+tuple_dbus_type!(A B C D E);
+tuple_dbus_type!(A B C D E F);
+tuple_dbus_type!(A B C D E F G);
+tuple_dbus_type!(A B C D E F G H);
+tuple_dbus_type!(A B C D E F G H I);
+tuple_dbus_type!(A B C D E F G H I J);
+tuple_dbus_type!(A B C D E F G H I J K);
+tuple_dbus_type!(A B C D E F G H I J K L);
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use types::{Value,BasicValue,Array,Path};
+    use signature::*;
+
+    #[test]
+    fn test_parse () {
+        assert_eq!(SignatureType::parse("u").unwrap(), SignatureType::Basic(BasicType::Uint32));
+        assert_eq!(SignatureType::parse("a(ib)").unwrap(), SignatureType::Array(Box::new(
+            SignatureType::Struct(vec![
+                SignatureType::Basic(BasicType::Int32),
+                SignatureType::Basic(BasicType::Boolean),
+            ])
+        )));
+        assert_eq!(SignatureType::parse("a{sv}").unwrap(), SignatureType::Dict(
+            BasicType::String, Box::new(SignatureType::Variant)
+        ));
+        assert_eq!(SignatureType::parse_list("sa{oas}v").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_validate () {
+        assert_eq!(validate(""), Ok(()));
+        assert_eq!(validate("sa{sv}(iau)"), Ok(()));
+        assert_eq!(validate("a{(i)s}"), Err(SignatureError::DictKeyNotBasic));
+        assert_eq!(validate("(i{su})"), Err(SignatureError::DictEntryOutsideArray));
+        assert_eq!(validate("a(i())"), Err(SignatureError::EmptyStruct));
+        assert_eq!(validate("m"), Err(SignatureError::UnknownTypeCode('m')));
+        assert_eq!(Signature::new(&"y".repeat(256)), Err(SignatureError::TooLong));
+
+        let deep_array = "a".repeat(32) + "y";
+        assert_eq!(validate(&deep_array), Ok(()));
+        assert_eq!(validate(&("a".to_string() + &deep_array)), Err(SignatureError::ArrayTooDeep));
+
+        let deep_struct = "(".repeat(32) + "y" + &")".repeat(32);
+        assert_eq!(validate(&deep_struct), Ok(()));
+        let deeper_struct = "a{y".to_string() + &deep_struct + "}";
+        assert_eq!(SignatureType::parse(&deeper_struct).unwrap().validate(),
+                   Err(SignatureError::StructTooDeep));
+    }
+
+    #[test]
+    fn test_value_type () {
+        let v = Value::Array(Array::new(vec![Value::BasicValue(BasicValue::Int64(1))]));
+        let elem = match v.get_type().unwrap() {
+            SignatureType::Array(x) => *x,
+            _ => panic!("Not an array")
+        };
+        assert_eq!(elem, SignatureType::Basic(BasicType::Int64));
+    }
+
+    #[test]
+    fn test_round_trip () {
+        for sig in &["y", "aay", "a{s(iav)}", "(yv(a{tt}))", "()", "aa{ha{dg}}"] {
+            assert_eq!(SignatureType::parse(sig).unwrap().to_string(), *sig);
+        }
+    }
+
+    #[test]
+    fn test_dbus_type () {
+        assert_eq!(u32::signature().to_string(), "u");
+        assert_eq!(<Vec<Vec<u8>>>::signature().to_string(), "aay");
+        assert_eq!(<HashMap<String,Vec<i64>>>::signature().to_string(), "a{sax}");
+        assert_eq!(<(u8,bool,f64,i16,u16,i32,u32,i64,u64,String,Path,Signature)>::signature().to_string(),
+                   "(ybdnqiuxtsog)");
+        assert_eq!(<Vec<(&str,HashMap<Path,(u32,)>)>>::signature().to_string(), "a(sa{o(u)})");
+        assert_eq!(<[Box<str>]>::signature().to_string(), "as");
+        assert_eq!(<Option<Vec<u8>>>::signature().to_string(), "may");
+    }
+
+    #[test]
+    fn test_gvariant () {
+        assert_eq!(SignatureType::parse("mi"), Err(SignatureError::UnknownTypeCode('m')));
+        assert_eq!(SignatureType::parse_gvariant("a{smi}").unwrap(), SignatureType::Dict(
+            BasicType::String, Box::new(SignatureType::Maybe(Box::new(SignatureType::Basic(BasicType::Int32))))
+        ));
+        assert_eq!(SignatureType::parse_gvariant("m(sm)"), Err(SignatureError::UnexpectedCharacter(')')));
+        assert_eq!(SignatureType::parse_gvariant("mmas").unwrap().to_string(), "mmas");
+    }
+
+    #[test]
+    fn test_errors () {
+        assert_eq!(SignatureType::parse(""), Err(SignatureError::Empty));
+        assert_eq!(SignatureType::parse("a"), Err(SignatureError::UnexpectedEnd));
+        assert_eq!(SignatureType::parse("(uu"), Err(SignatureError::UnexpectedEnd));
+        assert_eq!(SignatureType::parse("uu"), Err(SignatureError::TrailingCharacters));
+        assert_eq!(SignatureType::parse("z"), Err(SignatureError::UnknownTypeCode('z')));
+        assert_eq!(SignatureType::parse("u)"), Err(SignatureError::TrailingCharacters));
+        assert_eq!(SignatureType::parse_list("u)"), Err(SignatureError::UnexpectedCharacter(')')));
+        assert_eq!(SignatureType::parse("{sv}"), Err(SignatureError::DictEntryOutsideArray));
+        assert_eq!(SignatureType::parse("a{vs}"), Err(SignatureError::DictKeyNotBasic));
+        assert_eq!(SignatureType::parse("a{sss}"), Err(SignatureError::BadDictEntry));
+        assert_eq!(SignatureType::parse("(u}"), Err(SignatureError::UnexpectedCharacter('}')));
+
+        let deepest = "a".repeat(MAX_DEPTH) + "y";
+        assert!(SignatureType::parse(&deepest).is_ok());
+        assert_eq!(SignatureType::parse(&("a".to_string() + &deepest)), Err(SignatureError::TooDeep));
+        assert_eq!(SignatureType::parse_gvariant(&("m".repeat(1_000_000) + "y")), Err(SignatureError::TooDeep));
+    }
+}
@@ -0,0 +1,300 @@
+//! Strongly typed counterparts of the Variant, Array and Dictionary structs in the types module.
+//! Their signatures come from the Rust types they hold, so a DBusArray<String> is always "as",
+//! even when it is empty.
+//!
+//! DBusEncoder and DBusDecoder give the wrappers their D-Bus types.  Other rustc_serialize
+//! encoders and decoders see only the contents.
+#![allow(deprecated, clippy::redundant_field_names)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::thread::LocalKey;
+
+use rustc_serialize::{Decoder,Decodable,Encoder,Encodable};
+
+use decoder::{DBusDecoder,DecodeError};
+use encoder::{DBusEncoder,EncoderError};
+use signature::{DBusType,DBusBasicType,SignatureType};
+use types;
+use types::Value;
+
+/// The enum name under which the wrappers pass themselves to Encoder::emit_enum and
+/// Decoder::read_enum.  DBusEncoder and DBusDecoder take the type of the contents from TYPE_HINT.
+pub(crate) const TYPED_ENUM_NAME: &str = "dbus_serialize::typed";
+
+/// The D-Bus type of a wrapper's contents
+pub(crate) struct TypeHint {
+    pub t: SignatureType,
+    /// Whether the contents are boxed in a Variant
+    pub variant: bool
+}
+
+thread_local!(pub(crate) static TYPE_HINT: RefCell<Option<TypeHint>> = const { RefCell::new(None) });
+
+/// Fills one of the thread-local slots used to pass data past the Encoder and Decoder traits.
+/// Dropping the guard puts back whatever the slot held before, so the slot is left as it was
+/// found however the code in between exits, including by an error or a panic, and nested uses
+/// do not see each other's data.
+pub(crate) struct SlotGuard<T: 'static> {
+    slot: &'static LocalKey<RefCell<Option<T>>>,
+    prev: Option<T>
+}
+
+impl<T> SlotGuard<T> {
+    pub fn set(slot: &'static LocalKey<RefCell<Option<T>>>, value: T) -> SlotGuard<T> {
+        let prev = slot.with(|x| x.replace(Some(value)));
+        SlotGuard {
+            slot: slot,
+            prev: prev
+        }
+    }
+}
+
+impl<T> Drop for SlotGuard<T> {
+    fn drop(&mut self) {
+        let prev = self.prev.take();
+        // The slot may already be gone if this runs while the thread is exiting
+        let _ = self.slot.try_with(|x| *x.borrow_mut() = prev);
+    }
+}
+
+pub(crate) fn encode_as<T: Encodable, S: Encoder> (x: &T, t: SignatureType, variant: bool, s: &mut S) -> Result<(), S::Error> {
+    let _hint = SlotGuard::set(&TYPE_HINT, TypeHint { t: t, variant: variant });
+    s.emit_enum(TYPED_ENUM_NAME, |s| x.encode(s))
+}
+
+fn decode_as<T: Decodable, D: Decoder> (t: SignatureType, variant: bool, d: &mut D) -> Result<T, D::Error> {
+    let _hint = SlotGuard::set(&TYPE_HINT, TypeHint { t: t, variant: variant });
+    d.read_enum(TYPED_ENUM_NAME, T::decode)
+}
+
+/// A value of type T, sent as a D-Bus variant ('v') whose contents have the signature of T
+#[derive(Clone,Debug,PartialEq)]
+pub struct Variant<T>(pub T);
+
+/// An array whose element signature is that of T
+#[derive(Clone,Debug,PartialEq)]
+pub struct DBusArray<T>(pub Vec<T>);
+
+/// A dictionary whose key and value signatures are those of K and V
+#[derive(Clone,Debug,PartialEq)]
+pub struct DBusDict<K: Eq + Hash, V>(pub HashMap<K,V>);
+
+impl<T> DBusType for Variant<T> {
+    fn signature() -> SignatureType {
+        SignatureType::Variant
+    }
+}
+
+impl<T: DBusType> DBusType for DBusArray<T> {
+    fn signature() -> SignatureType {
+        SignatureType::Array(Box::new(T::signature()))
+    }
+}
+
+impl<K: DBusBasicType + Eq + Hash, V: DBusType> DBusType for DBusDict<K,V> {
+    fn signature() -> SignatureType {
+        SignatureType::Dict(K::basic_type(), Box::new(V::signature()))
+    }
+}
+
+impl<T: Encodable + DBusType> Encodable for Variant<T> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        encode_as(&self.0, T::signature(), true, s)
+    }
+}
+
+impl<T: Encodable + DBusType> Encodable for DBusArray<T> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        encode_as(&self.0, DBusArray::<T>::signature(), false, s)
+    }
+}
+
+impl<K: Encodable + DBusBasicType + Eq + Hash, V: Encodable + DBusType> Encodable for DBusDict<K,V> {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        encode_as(&self.0, DBusDict::<K,V>::signature(), false, s)
+    }
+}
+
+impl<T: Decodable + DBusType> Decodable for Variant<T> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        Ok(Variant(try!(decode_as(T::signature(), true, d))))
+    }
+}
+
+impl<T: Decodable + DBusType> Decodable for DBusArray<T> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        Ok(DBusArray(try!(decode_as(DBusArray::<T>::signature(), false, d))))
+    }
+}
+
+impl<K: Decodable + DBusBasicType + Eq + Hash, V: Decodable + DBusType> Decodable for DBusDict<K,V> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        Ok(DBusDict(try!(decode_as(DBusDict::<K,V>::signature(), false, d))))
+    }
+}
+
+impl<T: Encodable + DBusType> TryFrom<Variant<T>> for types::Variant {
+    type Error = EncoderError;
+
+    fn try_from(x: Variant<T>) -> Result<types::Variant,EncoderError> {
+        match try!(DBusEncoder::encode(&x)) {
+            Value::Variant(v) => Ok(v),
+            x => Err(EncoderError::mismatch("", &Variant::<T>::signature().to_string(), x.get_signature()))
+        }
+    }
+}
+
+impl<T: Encodable + DBusType> TryFrom<DBusArray<T>> for types::Array {
+    type Error = EncoderError;
+
+    fn try_from(x: DBusArray<T>) -> Result<types::Array,EncoderError> {
+        match try!(DBusEncoder::encode(&x)) {
+            Value::Array(v) => Ok(v),
+            x => Err(EncoderError::mismatch("", &DBusArray::<T>::signature().to_string(), x.get_signature()))
+        }
+    }
+}
+
+impl<K: Encodable + DBusBasicType + Eq + Hash, V: Encodable + DBusType> TryFrom<DBusDict<K,V>> for types::Dictionary {
+    type Error = EncoderError;
+
+    fn try_from(x: DBusDict<K,V>) -> Result<types::Dictionary,EncoderError> {
+        match try!(DBusEncoder::encode(&x)) {
+            Value::Dictionary(v) => Ok(v),
+            x => Err(EncoderError::mismatch("", &DBusDict::<K,V>::signature().to_string(), x.get_signature()))
+        }
+    }
+}
+
+impl<T: Decodable + DBusType> TryFrom<types::Variant> for Variant<T> {
+    type Error = DecodeError;
+
+    fn try_from(x: types::Variant) -> Result<Variant<T>,DecodeError> {
+        DBusDecoder::decode(Value::Variant(x))
+    }
+}
+
+impl<T: Decodable + DBusType> TryFrom<types::Array> for DBusArray<T> {
+    type Error = DecodeError;
+
+    fn try_from(x: types::Array) -> Result<DBusArray<T>,DecodeError> {
+        DBusDecoder::decode(Value::Array(x))
+    }
+}
+
+impl<K: Decodable + DBusBasicType + Eq + Hash, V: Decodable + DBusType> TryFrom<types::Dictionary> for DBusDict<K,V> {
+    type Error = DecodeError;
+
+    fn try_from(x: types::Dictionary) -> Result<DBusDict<K,V>,DecodeError> {
+        DBusDecoder::decode(Value::Dictionary(x))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use types;
+    use types::{Value,BasicValue,Struct,Signature};
+    use encoder::DBusEncoder;
+    use decoder::DBusDecoder;
+    use typed::*;
+
+    #[test]
+    fn test_variant () {
+        let v = DBusEncoder::encode(&(1u8, Variant(vec![2u32]))).unwrap();
+        let inner = types::Array::new(vec![Value::from(2u32)]);
+        assert_eq!(v, Value::Struct(Struct {
+            objects: vec![
+                Value::from(1u8),
+                Value::Variant(types::Variant::new(Value::Array(inner), "au")),
+            ],
+            signature: Signature("(yv)".to_string())
+        }));
+
+        let mut decoder = DBusDecoder::new(v);
+        decoder.set_unwrap_variants(false);
+        let x : (u8, Variant<Vec<u32>>) = decoder.decode_value().unwrap();
+        assert_eq!(x, (1, Variant(vec![2])));
+
+        let empty = types::Variant::try_from(Variant(Vec::<String>::new())).unwrap();
+        assert_eq!(empty.signature.0, "as");
+        assert_eq!(Variant::<Vec<String>>::try_from(empty).unwrap(), Variant(vec![]));
+    }
+
+    #[test]
+    fn test_array () {
+        let v = DBusEncoder::encode(&vec![DBusArray(vec![1u32]), DBusArray(vec![])]).unwrap();
+        assert_eq!(v, Value::Array(types::Array::new(vec![
+            Value::Array(types::Array::new(vec![Value::from(1u32)])),
+            Value::Array(types::Array::new_with_sig(vec![], "au".to_string())),
+        ])));
+        let x : Vec<DBusArray<u32>> = DBusDecoder::decode(v).unwrap();
+        assert_eq!(x, vec![DBusArray(vec![1]), DBusArray(vec![])]);
+
+        let a = types::Array::try_from(DBusArray(Vec::<types::Path>::new())).unwrap();
+        assert_eq!(a, types::Array::new_with_sig(vec![], "ao".to_string()));
+        assert_eq!(DBusArray::<types::Path>::try_from(a).unwrap(), DBusArray(vec![]));
+        let a = types::Array::new(vec![Value::from("x")]);
+        assert!(DBusArray::<u32>::try_from(a).is_err());
+    }
+
+    #[test]
+    fn test_dict () {
+        let mut props = HashMap::new();
+        props.insert("a".to_string(), Variant(DBusArray(Vec::<u8>::new())));
+        let d = types::Dictionary::try_from(DBusDict(props.clone())).unwrap();
+        assert_eq!(d, types::Dictionary::new_with_sig(vec![types::DictEntry {
+            key: BasicValue::String("a".to_string()),
+            value: Value::Variant(types::Variant::new(
+                Value::Array(types::Array::new_with_sig(vec![], "ay".to_string())), "ay"
+            ))
+        }], "a{sv}".to_string()));
+        assert_eq!(DBusDict::try_from(d).unwrap(), DBusDict(props));
+
+        let d = types::Dictionary::try_from(DBusDict(HashMap::<u32,bool>::new())).unwrap();
+        assert_eq!(d, types::Dictionary::new_with_sig(vec![], "a{ub}".to_string()));
+
+        // Other encoders see only the contents
+        let mut m = HashMap::new();
+        m.insert("k".to_string(), Variant(true));
+        assert_eq!(::rustc_serialize::json::encode(&DBusDict(m)).unwrap(), "{\"k\":true}");
+    }
+
+    struct Panics;
+
+    impl Encodable for Panics {
+        fn encode<S: Encoder>(&self, _s: &mut S) -> Result<(), S::Error> {
+            panic!("encode failed")
+        }
+    }
+
+    impl DBusType for Panics {
+        fn signature() -> SignatureType {
+            u32::signature()
+        }
+    }
+
+    fn hint_is_clear () -> bool {
+        TYPE_HINT.with(|x| x.borrow().is_none())
+    }
+
+    #[test]
+    fn test_no_stale_hint () {
+        // Other encoders never take the hint, so encode_as must clear it however it exits
+        let result = ::std::panic::catch_unwind(|| ::rustc_serialize::json::encode(&Variant(Panics)));
+        assert!(result.is_err());
+        assert!(hint_is_clear());
+
+        let x = Variant(DBusArray(vec![1u32]));
+        assert_eq!(::rustc_serialize::json::encode(&x).unwrap(), "[1]");
+        assert!(hint_is_clear());
+        assert_eq!(DBusEncoder::encode(&x).unwrap().get_signature(), "v");
+        assert!(hint_is_clear());
+        let y : Variant<DBusArray<u32>> = DBusDecoder::decode(DBusEncoder::encode(&x).unwrap()).unwrap();
+        assert_eq!(y, x);
+        assert!(hint_is_clear());
+    }
+}
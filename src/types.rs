@@ -1,4 +1,8 @@
 //! Contains the Value and BasicValue enums, as well as traits and helper types for them
+#![allow(deprecated, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
+use std::convert::TryFrom;
+
+use signature::{BasicType,DBusType,DBusBasicType,SignatureType,SignatureError};
 
 /// BasicValue covers the "basic" D-Bus types, that is those that are allowed to be used as keys in
 /// a dictionary.
@@ -18,9 +22,107 @@ pub enum BasicValue {
     Signature(Signature),
 }
 
+/// A D-Bus object path.  Paths built directly from the tuple constructor are not checked; use
+/// Path::new to check the syntax.
 #[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
 pub struct Path(pub String);
 
+#[derive(Debug,PartialEq)]
+pub enum PathError {
+    NoLeadingSlash,
+    EmptyElement,
+    TrailingSlash,
+    InvalidCharacter(char),
+}
+
+impl Path {
+    /// Create a new object path, checking that it is syntactically valid
+    pub fn new(path: &str) -> Result<Path,PathError> {
+        let p = Path(path.to_string());
+        try!(p.validate());
+        Ok(p)
+    }
+
+    /// Checks that the path starts with '/', has no empty elements and no trailing '/' (unless
+    /// it is the root path), and contains only [A-Za-z0-9_] in its elements
+    pub fn validate(&self) -> Result<(),PathError> {
+        if !self.0.starts_with('/') {
+            return Err(PathError::NoLeadingSlash);
+        }
+        if self.0 == "/" {
+            return Ok(());
+        }
+        if self.0.ends_with('/') {
+            return Err(PathError::TrailingSlash);
+        }
+        for elem in self.0[1..].split('/') {
+            if elem.is_empty() {
+                return Err(PathError::EmptyElement);
+            }
+            if let Some(c) = elem.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '_') {
+                return Err(PathError::InvalidCharacter(c));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the elements of the path.  The root path has no elements.
+    pub fn components(&self) -> Vec<&str> {
+        self.0.split('/').filter(|x| !x.is_empty()).collect()
+    }
+
+    /// Returns the path with its last element removed, or None for the root path
+    pub fn parent(&self) -> Option<Path> {
+        if self.0 == "/" {
+            return None;
+        }
+        match self.0.rfind('/') {
+            Some(0) => Some(Path("/".to_string())),
+            Some(x) => Some(Path(self.0[..x].to_string())),
+            None => None
+        }
+    }
+
+    /// Appends child to the path.  child is one or more path elements separated by '/', with no
+    /// leading or trailing '/'.
+    pub fn join(&self, child: &str) -> Result<Path,PathError> {
+        if child.starts_with('/') {
+            return Err(PathError::EmptyElement);
+        }
+        let mut path = self.0.clone();
+        if path != "/" {
+            path.push('/');
+        }
+        path.push_str(child);
+        Path::new(&path)
+    }
+
+    /// Returns true if prefix is this path or one of its ancestors.  Only whole elements are
+    /// compared, so "/a/bc" does not start with "/a/b".
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.strip_prefix(prefix).is_some()
+    }
+
+    /// Returns the part of this path below prefix, as a path rooted at "/", or None if prefix is
+    /// not this path or one of its ancestors
+    pub fn strip_prefix(&self, prefix: &Path) -> Option<Path> {
+        if prefix.0 == "/" {
+            return Some(self.clone());
+        }
+        if !self.0.starts_with(&prefix.0) {
+            return None;
+        }
+        let rest = &self.0[prefix.0.len()..];
+        if rest.is_empty() {
+            Some(Path("/".to_string()))
+        } else if rest.starts_with('/') {
+            Some(Path(rest.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Clone,PartialEq,Eq,Debug,Hash,PartialOrd,Ord)]
 pub struct Signature(pub String);
 
@@ -44,6 +146,38 @@ impl BasicValue {
     }
 }
 
+macro_rules! basic_value_from {
+    ($($t:ty => $variant:ident),*) => ($(
+        impl From<$t> for BasicValue {
+            fn from(x: $t) -> BasicValue {
+                BasicValue::$variant(x)
+            }
+        }
+
+        impl From<$t> for Value {
+            fn from(x: $t) -> Value {
+                Value::BasicValue(BasicValue::$variant(x))
+            }
+        }
+    )*)
+}
+
+basic_value_from!(u8 => Byte, bool => Boolean, f64 => Double, i16 => Int16, u16 => Uint16,
+                  i32 => Int32, u32 => Uint32, i64 => Int64, u64 => Uint64, String => String,
+                  Path => ObjectPath, Signature => Signature);
+
+impl From<&str> for BasicValue {
+    fn from(x: &str) -> BasicValue {
+        BasicValue::String(x.to_string())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(x: &str) -> Value {
+        Value::BasicValue(BasicValue::String(x.to_string()))
+    }
+}
+
 /// A Struct is an ordered sequence of Value objects, which may be of different varieties.
 /// signature must be of the form "(<type>)", where <type> is the signature of contents of
 /// objects.
@@ -72,6 +206,51 @@ impl Variant {
     }
 }
 
+/// GVariant's maybe type, which holds either nothing or a single value.  signature is the
+/// signature of the Maybe itself, e.g. "mi".  There is no maybe type in D-Bus, so these can only
+/// be serialized with the gvariant module.
+#[derive(PartialEq,Debug,Clone)]
+pub struct Maybe {
+    pub object: Option<Box<Value>>,
+    pub signature: Signature
+}
+
+impl Maybe {
+    /// Create a new maybe holding v, or nothing if v is None.  s is the signature of the
+    /// contained type, not of the maybe.
+    pub fn new (v: Option<Value>, s: &str) -> Maybe {
+        Maybe {
+            object: v.map(Box::new),
+            signature: Signature(format!("m{}", s))
+        }
+    }
+}
+
+/// Returned by the fallible Array and Dictionary constructors
+#[derive(Debug,PartialEq)]
+pub enum ContainerError {
+    /// The signature cannot be inferred because there are no elements
+    Empty,
+    /// The element at index does not have the same type as the rest of the container
+    ElementMismatch { index: usize, expected: String, found: String },
+    /// The given element type does not make a valid container signature
+    BadSignature(SignatureError),
+}
+
+fn check_elements<'a, I> (expected: &str, found: I) -> Result<(),ContainerError>
+    where I: Iterator<Item=&'a str> {
+    for (i, sig) in found.enumerate() {
+        if sig != expected {
+            return Err(ContainerError::ElementMismatch {
+                index: i,
+                expected: expected.to_string(),
+                found: sig.to_string()
+            });
+        }
+    }
+    Ok(())
+}
+
 /// An Array is an ordered sequence of Value objects which must all be of the same variety.  That
 /// is, it is not value to have a Uint8 and a Uint32 as elements of the same Array.
 #[derive(Clone,Debug,PartialEq)]
@@ -86,10 +265,11 @@ impl Array {
     /// determine the type signature for an empty vector.  Use new_with_sig instead.
     ///
     /// # Panics
-    /// If objects.len() is 0, this function will panic.
+    /// If objects.len() is 0, or the first element has an invalid signature, this function will
+    /// panic.  Array::try_from returns an error instead.
     pub fn new(objects: Vec<Value>) -> Array {
-        let inner_sig = objects.iter().next().unwrap().get_signature().to_string();
-        let sig = "a".to_string() + &inner_sig;
+        let elem = objects.first().unwrap().get_type().unwrap();
+        let sig = SignatureType::Array(Box::new(elem)).to_string();
         Array {
             objects: objects,
             signature: Signature(sig)
@@ -105,6 +285,46 @@ impl Array {
             signature: Signature(sig)
         }
     }
+
+    /// Like new_with_sig, but returns an error if sig is not a valid array signature.  The
+    /// contents of objects are not checked against sig.
+    pub fn try_new_with_sig(objects: Vec<Value>, sig: String) -> Result<Array,SignatureError> {
+        match try!(SignatureType::parse(&sig)) {
+            t @ SignatureType::Array(_) => try!(t.validate()),
+            _ => return Err(SignatureError::NotAnArray)
+        }
+        Ok(Array::new_with_sig(objects, sig))
+    }
+
+    /// Create a new array whose elements have type elem.  This works for empty vectors, and
+    /// returns an error if any of the objects are not of type elem.
+    pub fn with_element_type(objects: Vec<Value>, elem: &SignatureType) -> Result<Array,ContainerError> {
+        let t = SignatureType::Array(Box::new(elem.clone()));
+        try!(t.validate().map_err(ContainerError::BadSignature));
+        try!(check_elements(&elem.to_string(), objects.iter().map(|x| x.get_signature())));
+        Ok(Array::new_with_sig(objects, t.to_string()))
+    }
+
+    /// Create a new array of the Rust type T, taking the element signature from T rather than
+    /// from the contents of objects
+    pub fn new_typed<T: DBusType + ?Sized>(objects: Vec<Value>) -> Result<Array,ContainerError> {
+        Array::with_element_type(objects, &T::signature())
+    }
+}
+
+impl TryFrom<Vec<Value>> for Array {
+    type Error = ContainerError;
+
+    /// Like Array::new, but returns an error if objects is empty or its elements are not all of
+    /// the same type
+    fn try_from(objects: Vec<Value>) -> Result<Array,ContainerError> {
+        let elem = match objects.first() {
+            Some(x) => try!(x.get_type().map_err(ContainerError::BadSignature)),
+            None => return Err(ContainerError::Empty)
+        };
+        try!(check_elements(&elem.to_string(), objects.iter().map(|x| x.get_signature())));
+        Ok(Array::new_with_sig(objects, SignatureType::Array(Box::new(elem)).to_string()))
+    }
 }
 
 #[derive(Clone,Debug,PartialEq)]
@@ -129,11 +349,11 @@ impl Dictionary {
     /// instead.
     ///
     /// # Panics
-    /// If entries.len() is 0, this function will panic.
+    /// If entries.len() is 0, or the first value has an invalid signature, this function will
+    /// panic.  Dictionary::try_from returns an error instead.
     pub fn new(entries: Vec<DictEntry>) -> Dictionary {
-        let key_type = entries.first().unwrap().key.get_signature().to_string();
-        let val_type = entries.first().unwrap().value.get_signature().to_string();
-        let sig = "a{".to_string() + &key_type + &val_type + "}";
+        let first = entries.first().unwrap();
+        let sig = SignatureType::Dict(first.key.get_basic_type(), Box::new(first.value.get_type().unwrap())).to_string();
         Dictionary {
             entries: entries,
             signature: Signature(sig)
@@ -149,16 +369,136 @@ impl Dictionary {
             signature: Signature(sig)
         }
     }
+
+    /// Like new_with_sig, but returns an error if sig is not a valid dictionary signature.  The
+    /// contents of entries are not checked against sig.
+    pub fn try_new_with_sig(entries: Vec<DictEntry>, sig: String) -> Result<Dictionary,SignatureError> {
+        match try!(SignatureType::parse(&sig)) {
+            t @ SignatureType::Dict(_, _) => try!(t.validate()),
+            _ => return Err(SignatureError::NotADictionary)
+        }
+        Ok(Dictionary::new_with_sig(entries, sig))
+    }
+
+    /// Create a new Dictionary with the given key and value types.  This works for empty vectors,
+    /// and returns an error if any of the entries do not have those types.
+    pub fn with_entry_types(entries: Vec<DictEntry>, key: BasicType, value: &SignatureType)
+                            -> Result<Dictionary,ContainerError> {
+        let t = SignatureType::Dict(key, Box::new(value.clone()));
+        try!(t.validate().map_err(ContainerError::BadSignature));
+        try!(check_entries(&key.code().to_string(), &value.to_string(), &entries));
+        Ok(Dictionary::new_with_sig(entries, t.to_string()))
+    }
+
+    /// Create a new Dictionary with keys of Rust type K and values of Rust type V, taking the
+    /// signature from K and V rather than from the contents of entries
+    pub fn new_typed<K: DBusBasicType + ?Sized, V: DBusType + ?Sized>(entries: Vec<DictEntry>)
+                                                                     -> Result<Dictionary,ContainerError> {
+        Dictionary::with_entry_types(entries, K::basic_type(), &V::signature())
+    }
+}
+
+fn check_entries (key: &str, value: &str, entries: &[DictEntry]) -> Result<(),ContainerError> {
+    try!(check_elements(key, entries.iter().map(|x| x.key.get_signature())));
+    check_elements(value, entries.iter().map(|x| x.value.get_signature()))
+}
+
+impl TryFrom<Vec<DictEntry>> for Dictionary {
+    type Error = ContainerError;
+
+    /// Like Dictionary::new, but returns an error if entries is empty or the entries do not all
+    /// have the same key and value types
+    fn try_from(entries: Vec<DictEntry>) -> Result<Dictionary,ContainerError> {
+        let (key, value) = match entries.first() {
+            Some(x) => (x.key.get_basic_type(), try!(x.value.get_type().map_err(ContainerError::BadSignature))),
+            None => return Err(ContainerError::Empty)
+        };
+        try!(check_entries(&key.code().to_string(), &value.to_string(), &entries));
+        Ok(Dictionary::new_with_sig(entries, SignatureType::Dict(key, Box::new(value)).to_string()))
+    }
 }
 
-/// Root type for any D-Bus value
+/// Root type for any D-Bus value.  New variants may be added (Maybe was added in 0.2), so code
+/// outside this crate that matches on a Value needs a wildcard arm.
 #[derive(PartialEq,Debug,Clone)]
+#[non_exhaustive]
 pub enum Value {
     BasicValue(BasicValue),
     Array(Array),
     Variant(Variant),
     Struct(Struct),
-    Dictionary(Dictionary)
+    Dictionary(Dictionary),
+    Maybe(Maybe)
+}
+
+/// The problems that Value::validate can find
+#[derive(Debug,PartialEq)]
+pub enum ValidationErrorKind {
+    /// A container or variant signature is not a valid signature
+    BadSignature(SignatureError),
+    /// An Array signature is not of the form a<type>
+    NotAnArray,
+    /// A Dictionary signature is not of the form a{<type><type>}
+    NotADictionary,
+    /// A value does not have the type required by its container
+    TypeMismatch { expected: String, found: String },
+    /// A string contains a nul byte, which cannot be sent
+    StringContainsNul,
+    /// An ObjectPath value is not a valid object path
+    InvalidObjectPath(PathError),
+    /// A Signature value is not a valid signature
+    InvalidSignature(SignatureError),
+    /// A Maybe value, which GVariant supports but D-Bus does not
+    MaybeNotAllowed,
+}
+
+/// Returned by Value::validate.  location describes where the problem is, starting from the
+/// value that was validated, e.g. "[2].value.(1)" for the second field of the struct which is
+/// the value of the third dictionary entry.  It is empty if the problem is with the value itself.
+#[derive(Debug,PartialEq)]
+pub struct ValidationError {
+    pub location: String,
+    pub kind: ValidationErrorKind
+}
+
+fn validation_error (location: &str, kind: ValidationErrorKind) -> ValidationError {
+    ValidationError {
+        location: location.to_string(),
+        kind: kind
+    }
+}
+
+fn check_type (location: &str, expected: &str, found: &str) -> Result<(),ValidationError> {
+    if expected != found {
+        return Err(validation_error(location, ValidationErrorKind::TypeMismatch {
+            expected: expected.to_string(),
+            found: found.to_string()
+        }));
+    }
+    Ok(())
+}
+
+fn parse_container_sig (location: &str, sig: &str) -> Result<SignatureType,ValidationError> {
+    let t = try!(SignatureType::parse(sig).map_err(|e| validation_error(location, ValidationErrorKind::BadSignature(e))));
+    try!(t.validate().map_err(|e| validation_error(location, ValidationErrorKind::BadSignature(e))));
+    Ok(t)
+}
+
+impl BasicValue {
+    fn validate_at (&self, location: &str) -> Result<(),ValidationError> {
+        match self {
+            &BasicValue::String(ref x) if x.contains('\0') => {
+                Err(validation_error(location, ValidationErrorKind::StringContainsNul))
+            },
+            &BasicValue::ObjectPath(ref x) => {
+                x.validate().map_err(|e| validation_error(location, ValidationErrorKind::InvalidObjectPath(e)))
+            },
+            &BasicValue::Signature(ref x) => {
+                x.validate().map_err(|e| validation_error(location, ValidationErrorKind::InvalidSignature(e)))
+            },
+            _ => Ok(())
+        }
+    }
 }
 
 impl Value {
@@ -169,7 +509,71 @@ impl Value {
             &Value::Array(ref x) => &x.signature.0,
             &Value::Variant(_) => "v",
             &Value::Struct(ref x) => &x.signature.0,
-            &Value::Dictionary(ref x) => &x.signature.0
+            &Value::Dictionary(ref x) => &x.signature.0,
+            &Value::Maybe(ref x) => &x.signature.0
+        }
+    }
+
+    /// Checks that the value, and everything it contains, is a valid D-Bus value.  Array elements
+    /// and dictionary entries must match the container's signature, struct signatures must match
+    /// their fields, and variant signatures must match the boxed value.  Strings, object paths and
+    /// signatures are also checked.
+    pub fn validate(&self) -> Result<(),ValidationError> {
+        self.validate_at("")
+    }
+
+    fn validate_at (&self, location: &str) -> Result<(),ValidationError> {
+        match self {
+            &Value::BasicValue(ref x) => x.validate_at(location),
+            &Value::Array(ref x) => {
+                let elem = match try!(parse_container_sig(location, &x.signature.0)) {
+                    SignatureType::Array(t) => t.to_string(),
+                    _ => return Err(validation_error(location, ValidationErrorKind::NotAnArray))
+                };
+                for (i, obj) in x.objects.iter().enumerate() {
+                    let loc = format!("{}[{}]", location, i);
+                    try!(check_type(&loc, &elem, obj.get_signature()));
+                    try!(obj.validate_at(&loc));
+                }
+                Ok(())
+            },
+            &Value::Dictionary(ref x) => {
+                let (key, value) = match try!(parse_container_sig(location, &x.signature.0)) {
+                    SignatureType::Dict(k, v) => (k.code().to_string(), v.to_string()),
+                    _ => return Err(validation_error(location, ValidationErrorKind::NotADictionary))
+                };
+                for (i, entry) in x.entries.iter().enumerate() {
+                    let loc = format!("{}[{}].key", location, i);
+                    try!(check_type(&loc, &key, entry.key.get_signature()));
+                    try!(entry.key.validate_at(&loc));
+                    let loc = format!("{}[{}].value", location, i);
+                    try!(check_type(&loc, &value, entry.value.get_signature()));
+                    try!(entry.value.validate_at(&loc));
+                }
+                Ok(())
+            },
+            &Value::Struct(ref x) => {
+                try!(parse_container_sig(location, &x.signature.0));
+                let mut expected = "(".to_string();
+                for obj in x.objects.iter() {
+                    expected.push_str(obj.get_signature());
+                }
+                expected.push(')');
+                try!(check_type(location, &x.signature.0, &expected));
+                for (i, obj) in x.objects.iter().enumerate() {
+                    try!(obj.validate_at(&format!("{}.({})", location, i)));
+                }
+                Ok(())
+            },
+            &Value::Variant(ref x) => {
+                try!(parse_container_sig(location, &x.signature.0));
+                let loc = format!("{}.<{}>", location, x.signature.0);
+                try!(check_type(&loc, &x.signature.0, x.object.get_signature()));
+                x.object.validate_at(&loc)
+            },
+            &Value::Maybe(_) => {
+                Err(validation_error(location, ValidationErrorKind::MaybeNotAllowed))
+            }
         }
     }
 }
@@ -181,3 +585,135 @@ fn test_from () {
     let y = Value::from("foobar");
     assert_eq!(y, Value::BasicValue(BasicValue::String("foobar".to_string())));
 }
+
+#[test]
+fn test_checked_sig () {
+    assert!(Array::try_new_with_sig(vec![], "ai".to_string()).is_ok());
+    assert_eq!(Array::try_new_with_sig(vec![], "a{si}".to_string()), Err(SignatureError::NotAnArray));
+    assert_eq!(Array::try_new_with_sig(vec![], "a(".to_string()), Err(SignatureError::UnexpectedEnd));
+    assert!(Dictionary::try_new_with_sig(vec![], "a{si}".to_string()).is_ok());
+    assert_eq!(Dictionary::try_new_with_sig(vec![], "a{s()}".to_string()), Err(SignatureError::EmptyStruct));
+    assert_eq!(Dictionary::try_new_with_sig(vec![], "as".to_string()), Err(SignatureError::NotADictionary));
+}
+
+#[test]
+fn test_fallible_constructors () {
+    let int = Value::BasicValue(BasicValue::Int32(1));
+    let string = Value::BasicValue(BasicValue::String("x".to_string()));
+    assert_eq!(Array::try_from(vec![]), Err(ContainerError::Empty));
+    assert_eq!(Array::try_from(vec![int.clone()]).unwrap().signature.0, "ai");
+    assert_eq!(Array::try_from(vec![int.clone(), string.clone()]), Err(ContainerError::ElementMismatch {
+        index: 1, expected: "i".to_string(), found: "s".to_string()
+    }));
+    let maybe = Value::Maybe(Maybe::new(None, "i"));
+    assert_eq!(Array::try_from(vec![maybe]).unwrap().signature.0, "ami");
+    let bad = Value::Array(Array::new_with_sig(vec![], "a(".to_string()));
+    assert_eq!(Array::try_from(vec![bad]), Err(ContainerError::BadSignature(SignatureError::UnexpectedEnd)));
+
+    let t = SignatureType::parse("(is)").unwrap();
+    assert_eq!(Array::with_element_type(vec![], &t).unwrap().signature.0, "a(is)");
+    assert_eq!(Array::with_element_type(vec![int.clone()], &t).unwrap_err(), ContainerError::ElementMismatch {
+        index: 0, expected: "(is)".to_string(), found: "i".to_string()
+    });
+    assert_eq!(Array::with_element_type(vec![], &SignatureType::Struct(vec![])),
+               Err(ContainerError::BadSignature(SignatureError::EmptyStruct)));
+
+    assert_eq!(Dictionary::try_from(vec![]), Err(ContainerError::Empty));
+    let entries = vec![
+        DictEntry { key: BasicValue::Byte(1), value: int.clone() },
+        DictEntry { key: BasicValue::Byte(2), value: string.clone() },
+    ];
+    assert_eq!(Dictionary::try_from(entries).unwrap_err(), ContainerError::ElementMismatch {
+        index: 1, expected: "i".to_string(), found: "s".to_string()
+    });
+    let dict = Dictionary::with_entry_types(vec![], BasicType::String, &SignatureType::Variant).unwrap();
+    assert_eq!(dict.signature.0, "a{sv}");
+
+    assert_eq!(Array::new_typed::<(i32,String)>(vec![]).unwrap().signature.0, "a(is)");
+    assert!(Array::new_typed::<u32>(vec![int.clone()]).is_err());
+    let dict = Dictionary::new_typed::<Path,Vec<u8>>(vec![]).unwrap();
+    assert_eq!(dict.signature.0, "a{oay}");
+}
+
+#[test]
+fn test_value_validate () {
+    let int = Value::BasicValue(BasicValue::Int32(1));
+    let string = Value::BasicValue(BasicValue::String("x".to_string()));
+    assert_eq!(Value::Array(Array::new(vec![int.clone(), int.clone()])).validate(), Ok(()));
+
+    let arr = Value::Array(Array::new(vec![int.clone(), string.clone()]));
+    assert_eq!(arr.validate(), Err(ValidationError {
+        location: "[1]".to_string(),
+        kind: ValidationErrorKind::TypeMismatch { expected: "i".to_string(), found: "s".to_string() }
+    }));
+
+    let st = Value::Struct(Struct {
+        objects: vec![int.clone(), string.clone()],
+        signature: Signature("(is)".to_string())
+    });
+    assert_eq!(st.validate(), Ok(()));
+    let bad_st = Value::Struct(Struct {
+        objects: vec![int.clone()],
+        signature: Signature("(is)".to_string())
+    });
+    let dict = Value::Dictionary(Dictionary::new(vec![
+        DictEntry { key: BasicValue::Byte(1), value: st.clone() },
+        DictEntry { key: BasicValue::Byte(2), value: bad_st },
+    ]));
+    assert_eq!(dict.validate(), Err(ValidationError {
+        location: "[1].value".to_string(),
+        kind: ValidationErrorKind::TypeMismatch { expected: "(is)".to_string(), found: "(i)".to_string() }
+    }));
+
+    let var = Value::Variant(Variant::new(Value::Struct(Struct {
+        objects: vec![Value::BasicValue(BasicValue::ObjectPath(Path("bad".to_string())))],
+        signature: Signature("(o)".to_string())
+    }), "(o)"));
+    assert_eq!(var.validate(), Err(ValidationError {
+        location: ".<(o)>.(0)".to_string(),
+        kind: ValidationErrorKind::InvalidObjectPath(PathError::NoLeadingSlash)
+    }));
+    let var = Value::Variant(Variant::new(int.clone(), "u"));
+    assert_eq!(var.validate().unwrap_err().location, ".<u>");
+
+    let arr = Value::Array(Array::new_with_sig(vec![], "a{si}".to_string()));
+    assert_eq!(arr.validate().unwrap_err().kind, ValidationErrorKind::NotAnArray);
+
+    let maybe = Value::Maybe(Maybe::new(None, "mi"));
+    assert_eq!(maybe.validate().unwrap_err().kind, ValidationErrorKind::MaybeNotAllowed);
+}
+
+#[test]
+fn test_path_validate () {
+    assert!(Path::new("/").is_ok());
+    assert!(Path::new("/org/freedesktop/DBus_1").is_ok());
+    assert_eq!(Path::new(""), Err(PathError::NoLeadingSlash));
+    assert_eq!(Path::new("org"), Err(PathError::NoLeadingSlash));
+    assert_eq!(Path::new("/org/"), Err(PathError::TrailingSlash));
+    assert_eq!(Path::new("/org//a"), Err(PathError::EmptyElement));
+    assert_eq!(Path::new("/org/a-b"), Err(PathError::InvalidCharacter('-')));
+}
+
+#[test]
+fn test_path_manipulation () {
+    let root = Path::new("/").unwrap();
+    let p = Path::new("/org/example").unwrap();
+    assert_eq!(p.components(), vec!["org", "example"]);
+    assert!(root.components().is_empty());
+    assert_eq!(p.parent(), Some(Path("/org".to_string())));
+    assert_eq!(p.parent().unwrap().parent(), Some(root.clone()));
+    assert_eq!(root.parent(), None);
+
+    assert_eq!(root.join("org"), Ok(Path("/org".to_string())));
+    assert_eq!(p.join("a/b"), Ok(Path("/org/example/a/b".to_string())));
+    assert_eq!(p.join("/a"), Err(PathError::EmptyElement));
+    assert_eq!(p.join("a/"), Err(PathError::TrailingSlash));
+
+    let child = Path::new("/org/example/obj").unwrap();
+    assert!(child.starts_with(&p));
+    assert!(child.starts_with(&root));
+    assert!(!Path::new("/org/examples").unwrap().starts_with(&p));
+    assert_eq!(child.strip_prefix(&p), Some(Path("/obj".to_string())));
+    assert_eq!(p.strip_prefix(&p), Some(root.clone()));
+    assert_eq!(p.strip_prefix(&child), None);
+}
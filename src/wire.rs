@@ -0,0 +1,782 @@
+//! Converts Value objects to and from the binary D-Bus wire format
+#![allow(deprecated, clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::redundant_field_names)]
+use std;
+
+use types::{Value,BasicValue,Path,Signature,Struct,Variant,Array,Dictionary,DictEntry};
+use signature::{SignatureType,BasicType};
+
+/// Maximum length in bytes of a marshalled array, as set by the D-Bus specification
+pub const MAX_ARRAY_LEN: usize = 67108864;
+
+/// Maximum nesting depth of containers (including variants) accepted when unmarshalling
+pub const MAX_DEPTH: usize = 64;
+
+/// Byte order used for multi-byte values.  D-Bus peers may use either; each message states its
+/// byte order in the first byte of its header.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// Returns the byte order of the host
+    pub fn native() -> Endianness {
+        if cfg!(target_endian = "big") {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
+    }
+
+    /// Returns the endianness flag used in a D-Bus message header ('l' or 'B')
+    pub fn to_flag(&self) -> u8 {
+        match self {
+            &Endianness::Little => b'l',
+            &Endianness::Big => b'B',
+        }
+    }
+
+    /// Parses the endianness flag from a D-Bus message header
+    pub fn from_flag(flag: u8) -> Option<Endianness> {
+        match flag {
+            b'l' => Some(Endianness::Little),
+            b'B' => Some(Endianness::Big),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug,PartialEq)]
+pub enum WireError {
+    BadSignature,
+    StringContainsNul,
+    ArrayTooLong,
+    UnexpectedEof,
+    NonZeroPadding,
+    InvalidUtf8,
+    InvalidBoolean,
+    BadArrayLength,
+    TooDeep,
+    InvalidObjectPath,
+}
+
+/// Returns the alignment of the type whose signature starts with the given type code
+pub(crate) fn alignment (code: u8) -> Result<usize,WireError> {
+    let align = match code {
+        b'y' | b'g' | b'v' => 1,
+        b'n' | b'q' => 2,
+        b'b' | b'i' | b'u' | b's' | b'o' | b'a' | b'h' => 4,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => return Err(WireError::BadSignature)
+    };
+    Ok(align)
+}
+
+/// Returns the alignment of a parsed type
+pub(crate) fn type_alignment (t: &SignatureType) -> usize {
+    match t {
+        &SignatureType::Basic(x) => alignment(x.code() as u8).unwrap_or(1),
+        &SignatureType::Array(_) | &SignatureType::Dict(_, _) => 4,
+        &SignatureType::Struct(_) => 8,
+        &SignatureType::Variant | &SignatureType::Maybe(_) => 1,
+    }
+}
+
+/// Writes Values into a byte buffer using the D-Bus marshalling rules.  Padding is computed
+/// relative to the start of the buffer, so a buffer should correspond to a complete message (or a
+/// message body, which always starts on an 8-byte boundary).
+pub struct WireEncoder {
+    buf: Vec<u8>,
+    endian: Endianness
+}
+
+impl WireEncoder {
+    pub(crate) fn pad (&mut self, align: usize) {
+        while !self.buf.len().is_multiple_of(align) {
+            self.buf.push(0);
+        }
+    }
+
+    pub(crate) fn write_u8 (&mut self, x: u8) {
+        self.buf.push(x);
+    }
+
+    pub(crate) fn write_u16 (&mut self, x: u16) {
+        self.pad(2);
+        let bytes = match self.endian {
+            Endianness::Little => x.to_le_bytes(),
+            Endianness::Big => x.to_be_bytes(),
+        };
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    pub(crate) fn write_u32 (&mut self, x: u32) {
+        self.pad(4);
+        let bytes = match self.endian {
+            Endianness::Little => x.to_le_bytes(),
+            Endianness::Big => x.to_be_bytes(),
+        };
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    pub(crate) fn write_u64 (&mut self, x: u64) {
+        self.pad(8);
+        let bytes = match self.endian {
+            Endianness::Little => x.to_le_bytes(),
+            Endianness::Big => x.to_be_bytes(),
+        };
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    pub(crate) fn write_str (&mut self, s: &str) -> Result<(),WireError> {
+        if s.as_bytes().contains(&0) {
+            return Err(WireError::StringContainsNul);
+        }
+        self.write_u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+        Ok(())
+    }
+
+    pub(crate) fn write_sig (&mut self, s: &str) -> Result<(),WireError> {
+        if s.len() > 255 || s.as_bytes().contains(&0) {
+            return Err(WireError::BadSignature);
+        }
+        self.buf.push(s.len() as u8);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+        Ok(())
+    }
+
+    pub(crate) fn write_basic (&mut self, v: &BasicValue) -> Result<(),WireError> {
+        match v {
+            &BasicValue::Byte(x) => self.write_u8(x),
+            &BasicValue::Boolean(x) => self.write_u32(x as u32),
+            &BasicValue::Double(x) => self.write_u64(x.to_bits()),
+            &BasicValue::Int16(x) => self.write_u16(x as u16),
+            &BasicValue::Uint16(x) => self.write_u16(x),
+            &BasicValue::Int32(x) => self.write_u32(x as u32),
+            &BasicValue::Uint32(x) => self.write_u32(x),
+            &BasicValue::Int64(x) => self.write_u64(x as u64),
+            &BasicValue::Uint64(x) => self.write_u64(x),
+            &BasicValue::String(ref x) => try!(self.write_str(x)),
+            &BasicValue::ObjectPath(ref x) => {
+                if x.validate().is_err() {
+                    return Err(WireError::InvalidObjectPath);
+                }
+                try!(self.write_str(&x.0))
+            },
+            &BasicValue::Signature(ref x) => {
+                if x.validate().is_err() {
+                    return Err(WireError::BadSignature);
+                }
+                try!(self.write_sig(&x.0))
+            },
+        }
+        Ok(())
+    }
+
+    /// Writes an array header with a placeholder length.  Returns the position of the length,
+    /// which must be passed to end_array once the elements have been written.
+    pub(crate) fn begin_array (&mut self, elem_align: usize) -> usize {
+        self.write_u32(0);
+        let len_pos = self.buf.len() - 4;
+        // The padding before the first element is not counted in the array length
+        self.pad(elem_align);
+        len_pos
+    }
+
+    /// Fills in the length of the array started by begin_array
+    pub(crate) fn end_array (&mut self, len_pos: usize, elem_align: usize) -> Result<(),WireError> {
+        let mut start = len_pos + 4;
+        while !start.is_multiple_of(elem_align) {
+            start += 1;
+        }
+        let len = self.buf.len() - start;
+        if len > MAX_ARRAY_LEN {
+            return Err(WireError::ArrayTooLong);
+        }
+        let len_bytes = match self.endian {
+            Endianness::Little => (len as u32).to_le_bytes(),
+            Endianness::Big => (len as u32).to_be_bytes(),
+        };
+        self.buf[len_pos..len_pos+4].copy_from_slice(&len_bytes);
+        Ok(())
+    }
+
+    /// Writes an array header, calls f to write the elements, then fills in the length.  sig is
+    /// the signature of the whole array.
+    fn write_array<F> (&mut self, sig: &str, f: F) -> Result<(),WireError>
+            where F: FnOnce(&mut Self) -> Result<(),WireError> {
+        let elem_align = match sig.as_bytes() {
+            [b'a', code, ..] => try!(alignment(*code)),
+            _ => return Err(WireError::BadSignature)
+        };
+
+        let len_pos = self.begin_array(elem_align);
+        try!(f(self));
+        self.end_array(len_pos, elem_align)
+    }
+
+    pub fn new(endian: Endianness) -> WireEncoder {
+        WireEncoder {
+            buf: Vec::new(),
+            endian: endian
+        }
+    }
+
+    /// Marshals a single value into a new buffer
+    pub fn encode(v: &Value, endian: Endianness) -> Result<Vec<u8>,WireError> {
+        let mut encoder = WireEncoder::new(endian);
+        try!(encoder.write_value(v));
+        Ok(encoder.into_bytes())
+    }
+
+    /// Appends the marshalled form of v to the buffer
+    pub fn write_value(&mut self, v: &Value) -> Result<(),WireError> {
+        match v {
+            &Value::BasicValue(ref x) => self.write_basic(x),
+            &Value::Array(ref x) => {
+                let sig = v.get_signature();
+                let elem_sig = sig.get(1..).unwrap_or("");
+                if x.objects.iter().any(|obj| obj.get_signature() != elem_sig) {
+                    return Err(WireError::BadSignature);
+                }
+                self.write_array(sig, |s| {
+                    for obj in x.objects.iter() {
+                        try!(s.write_value(obj));
+                    }
+                    Ok(())
+                })
+            },
+            &Value::Dictionary(ref x) => {
+                let sig = v.get_signature();
+                // The entry signature, e.g. "sv" for "a{sv}"
+                let entry_sig = sig.get(2..sig.len().saturating_sub(1)).unwrap_or("");
+                if x.entries.iter().any(|e| !entry_sig.starts_with(e.key.get_signature()) ||
+                                            &entry_sig[1..] != e.value.get_signature()) {
+                    return Err(WireError::BadSignature);
+                }
+                self.write_array(sig, |s| {
+                    for entry in x.entries.iter() {
+                        s.pad(8);
+                        try!(s.write_basic(&entry.key));
+                        try!(s.write_value(&entry.value));
+                    }
+                    Ok(())
+                })
+            },
+            &Value::Struct(ref x) => {
+                let fields = match x.signature.parse() {
+                    Ok(SignatureType::Struct(fields)) => fields,
+                    _ => return Err(WireError::BadSignature)
+                };
+                if fields.len() != x.objects.len() ||
+                        fields.iter().zip(x.objects.iter()).any(|(t, obj)| t.to_string() != obj.get_signature()) {
+                    return Err(WireError::BadSignature);
+                }
+                self.pad(8);
+                for obj in x.objects.iter() {
+                    try!(self.write_value(obj));
+                }
+                Ok(())
+            },
+            &Value::Variant(ref x) => {
+                if x.object.get_signature() != x.signature.0 {
+                    return Err(WireError::BadSignature);
+                }
+                try!(self.write_sig(&x.signature.0));
+                self.write_value(&x.object)
+            },
+            // D-Bus has no maybe type
+            &Value::Maybe(_) => Err(WireError::BadSignature),
+        }
+    }
+
+    /// Returns the marshalled data
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads Values out of a byte buffer that uses the D-Bus marshalling rules.  As with
+/// WireEncoder, padding is computed relative to the start of the buffer.
+pub struct WireDecoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    depth: usize,
+    endian: Endianness
+}
+
+impl<'a> WireDecoder<'a> {
+    pub(crate) fn skip_padding (&mut self, align: usize) -> Result<(),WireError> {
+        while !self.pos.is_multiple_of(align) {
+            match self.buf.get(self.pos) {
+                Some(&0) => self.pos += 1,
+                Some(_) => return Err(WireError::NonZeroPadding),
+                None => return Err(WireError::UnexpectedEof)
+            }
+        }
+        Ok(())
+    }
+
+    fn read_bytes (&mut self, len: usize) -> Result<&'a [u8],WireError> {
+        if self.pos > self.buf.len() || self.buf.len() - self.pos < len {
+            return Err(WireError::UnexpectedEof);
+        }
+        let buf = self.buf;
+        let bytes = &buf[self.pos..self.pos+len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_u8 (&mut self) -> Result<u8,WireError> {
+        Ok(try!(self.read_bytes(1))[0])
+    }
+
+    pub(crate) fn read_u16 (&mut self) -> Result<u16,WireError> {
+        try!(self.skip_padding(2));
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(try!(self.read_bytes(2)));
+        let x = match self.endian {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        };
+        Ok(x)
+    }
+
+    pub(crate) fn read_u32 (&mut self) -> Result<u32,WireError> {
+        try!(self.skip_padding(4));
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(try!(self.read_bytes(4)));
+        let x = match self.endian {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        };
+        Ok(x)
+    }
+
+    pub(crate) fn read_u64 (&mut self) -> Result<u64,WireError> {
+        try!(self.skip_padding(8));
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(try!(self.read_bytes(8)));
+        let x = match self.endian {
+            Endianness::Little => u64::from_le_bytes(bytes),
+            Endianness::Big => u64::from_be_bytes(bytes),
+        };
+        Ok(x)
+    }
+
+    /// Reads len bytes of string data followed by a nul terminator
+    fn read_str_data (&mut self, len: usize) -> Result<&'a str,WireError> {
+        let bytes = try!(self.read_bytes(len));
+        if try!(self.read_u8()) != 0 || bytes.contains(&0) {
+            return Err(WireError::StringContainsNul);
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(x) => Ok(x),
+            Err(_) => Err(WireError::InvalidUtf8)
+        }
+    }
+
+    /// Reads a string without copying it out of the buffer
+    pub(crate) fn read_str_ref (&mut self) -> Result<&'a str,WireError> {
+        let len = try!(self.read_u32()) as usize;
+        self.read_str_data(len)
+    }
+
+    /// Reads a signature without copying it out of the buffer
+    pub(crate) fn read_sig_ref (&mut self) -> Result<&'a str,WireError> {
+        let len = try!(self.read_u8()) as usize;
+        self.read_str_data(len)
+    }
+
+    fn read_str (&mut self) -> Result<String,WireError> {
+        Ok(try!(self.read_str_ref()).to_string())
+    }
+
+    fn read_sig (&mut self) -> Result<String,WireError> {
+        Ok(try!(self.read_sig_ref()).to_string())
+    }
+
+    fn read_basic (&mut self, t: BasicType) -> Result<BasicValue,WireError> {
+        let val = match t {
+            BasicType::Byte => BasicValue::Byte(try!(self.read_u8())),
+            BasicType::Boolean => match try!(self.read_u32()) {
+                0 => BasicValue::Boolean(false),
+                1 => BasicValue::Boolean(true),
+                _ => return Err(WireError::InvalidBoolean)
+            },
+            BasicType::Double => BasicValue::Double(f64::from_bits(try!(self.read_u64()))),
+            BasicType::Int16 => BasicValue::Int16(try!(self.read_u16()) as i16),
+            BasicType::Uint16 => BasicValue::Uint16(try!(self.read_u16())),
+            BasicType::Int32 => BasicValue::Int32(try!(self.read_u32()) as i32),
+            BasicType::Uint32 => BasicValue::Uint32(try!(self.read_u32())),
+            BasicType::Int64 => BasicValue::Int64(try!(self.read_u64()) as i64),
+            BasicType::Uint64 => BasicValue::Uint64(try!(self.read_u64())),
+            BasicType::String => BasicValue::String(try!(self.read_str())),
+            BasicType::ObjectPath => {
+                let path = Path(try!(self.read_str()));
+                if path.validate().is_err() {
+                    return Err(WireError::InvalidObjectPath);
+                }
+                BasicValue::ObjectPath(path)
+            },
+            BasicType::Signature => BasicValue::Signature(Signature(try!(self.read_sig()))),
+            BasicType::UnixFd => return Err(WireError::BadSignature)
+        };
+        Ok(val)
+    }
+
+    /// Reads an array header and returns the position of the end of the array contents.
+    /// elem_align is the alignment of a single element.
+    pub(crate) fn begin_array (&mut self, elem_align: usize) -> Result<usize,WireError> {
+        let len = try!(self.read_u32()) as usize;
+        if len > MAX_ARRAY_LEN {
+            return Err(WireError::ArrayTooLong);
+        }
+        try!(self.skip_padding(elem_align));
+        if self.pos > self.buf.len() || self.buf.len() - self.pos < len {
+            return Err(WireError::UnexpectedEof);
+        }
+        Ok(self.pos + len)
+    }
+
+    /// Reads an array header, then calls f repeatedly until the array contents are consumed.
+    /// elem_align is the alignment of a single element.
+    fn read_array<F> (&mut self, elem_align: usize, mut f: F) -> Result<(),WireError>
+            where F: FnMut(&mut Self) -> Result<(),WireError> {
+        let end = try!(self.begin_array(elem_align));
+        while self.pos < end {
+            try!(f(self));
+        }
+        if self.pos != end {
+            return Err(WireError::BadArrayLength);
+        }
+        Ok(())
+    }
+
+    /// Records entry into a container, failing if the nesting limit has been reached.  Each call
+    /// must be matched by a call to leave.
+    pub(crate) fn enter (&mut self) -> Result<(),WireError> {
+        if self.depth == MAX_DEPTH {
+            return Err(WireError::TooDeep);
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave (&mut self) {
+        self.depth -= 1;
+    }
+
+    fn read_container (&mut self, t: &SignatureType) -> Result<Value,WireError> {
+        match t {
+            &SignatureType::Basic(x) => Ok(Value::BasicValue(try!(self.read_basic(x)))),
+            &SignatureType::Dict(key_type, ref value_type) => {
+                let mut entries = Vec::new();
+                try!(self.read_array(8, |s| {
+                    try!(s.skip_padding(8));
+                    let key = try!(s.read_basic(key_type));
+                    let value = try!(s.read_type(value_type));
+                    entries.push(DictEntry { key: key, value: value });
+                    Ok(())
+                }));
+                Ok(Value::Dictionary(Dictionary::new_with_sig(entries, t.to_string())))
+            },
+            &SignatureType::Array(ref elem_type) => {
+                let mut objects = Vec::new();
+                try!(self.read_array(type_alignment(elem_type), |s| {
+                    objects.push(try!(s.read_type(elem_type)));
+                    Ok(())
+                }));
+                Ok(Value::Array(Array::new_with_sig(objects, t.to_string())))
+            },
+            &SignatureType::Struct(ref fields) => {
+                if fields.is_empty() {
+                    return Err(WireError::BadSignature);
+                }
+                try!(self.skip_padding(8));
+                let mut objects = Vec::new();
+                for field in fields.iter() {
+                    objects.push(try!(self.read_type(field)));
+                }
+                Ok(Value::Struct(Struct {
+                    objects: objects,
+                    signature: Signature(t.to_string())
+                }))
+            },
+            &SignatureType::Variant => {
+                let inner_sig = try!(self.read_sig());
+                let object = try!(self.read_value(&inner_sig));
+                Ok(Value::Variant(Variant::new(object, &inner_sig)))
+            },
+            &SignatureType::Maybe(_) => Err(WireError::BadSignature),
+        }
+    }
+
+    pub fn new(buf: &'a [u8], offset: usize, endian: Endianness) -> WireDecoder<'a> {
+        WireDecoder {
+            buf: buf,
+            pos: offset,
+            depth: 0,
+            endian: endian
+        }
+    }
+
+    /// Unmarshals a single value of type sig, starting at offset in buf
+    pub fn decode(buf: &[u8], offset: usize, sig: &str, endian: Endianness) -> Result<Value,WireError> {
+        let mut decoder = WireDecoder::new(buf, offset, endian);
+        decoder.read_value(sig)
+    }
+
+    /// Reads the next value from the buffer.  sig must be a single complete type.
+    pub fn read_value(&mut self, sig: &str) -> Result<Value,WireError> {
+        match SignatureType::parse(sig) {
+            Ok(ref t) if t.validate().is_ok() => self.read_type(t),
+            _ => Err(WireError::BadSignature)
+        }
+    }
+
+    /// Reads the next value, of type t, from the buffer
+    pub fn read_type(&mut self, t: &SignatureType) -> Result<Value,WireError> {
+        if t.is_basic() {
+            return self.read_container(t);
+        }
+        try!(self.enter());
+        let result = self.read_container(t);
+        self.leave();
+        result
+    }
+
+    /// Returns the offset of the next unread byte
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use types::{Value,BasicValue,Path,Struct,Signature,Variant,Array,Dictionary,DictEntry};
+    use wire::*;
+
+    #[test]
+    fn test_basic () {
+        let v = Value::BasicValue(BasicValue::Uint32(0x01020304));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![4,3,2,1]);
+
+        let v = Value::BasicValue(BasicValue::Boolean(true));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![1,0,0,0]);
+
+        let v = Value::BasicValue(BasicValue::String("foo".to_string()));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![3,0,0,0,b'f',b'o',b'o',0]);
+
+        let v = Value::BasicValue(BasicValue::Signature(Signature("ai".to_string())));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![2,b'a',b'i',0]);
+    }
+
+    #[test]
+    fn test_big_endian () {
+        let v = Value::BasicValue(BasicValue::Uint32(0x01020304));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Big).unwrap(), vec![1,2,3,4]);
+
+        let v = Value::Array(Array::new(vec![
+            Value::BasicValue(BasicValue::Int16(-2)),
+        ]));
+        let bytes = WireEncoder::encode(&v, Endianness::Big).unwrap();
+        assert_eq!(bytes, vec![0,0,0,2, 0xff,0xfe]);
+        assert_eq!(WireDecoder::decode(&bytes, 0, "an", Endianness::Big).unwrap(), v);
+        assert!(WireDecoder::decode(&bytes, 0, "an", Endianness::Little).is_err());
+    }
+
+    #[test]
+    fn test_endianness_flag () {
+        assert_eq!(Endianness::from_flag(b'l'), Some(Endianness::Little));
+        assert_eq!(Endianness::from_flag(b'B'), Some(Endianness::Big));
+        assert_eq!(Endianness::from_flag(b'x'), None);
+        assert_eq!(Endianness::Big.to_flag(), b'B');
+    }
+
+    #[test]
+    fn test_struct_padding () {
+        let v = Value::Struct(Struct {
+            objects: vec![
+                Value::BasicValue(BasicValue::Byte(1)),
+                Value::BasicValue(BasicValue::Uint16(2)),
+                Value::BasicValue(BasicValue::Uint64(3)),
+            ],
+            signature: Signature("(yqt)".to_string())
+        });
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![
+            1,0, 2,0, 0,0,0,0,
+            3,0,0,0,0,0,0,0,
+        ]);
+
+        let v = Value::Struct(Struct {
+            objects: vec![Value::BasicValue(BasicValue::Uint32(1))],
+            signature: Signature("(s)".to_string())
+        });
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::BadSignature));
+
+        let v = Value::Struct(Struct {
+            objects: vec![Value::BasicValue(BasicValue::Uint32(1))],
+            signature: Signature("(uu)".to_string())
+        });
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::BadSignature));
+    }
+
+    #[test]
+    fn test_array () {
+        let v = Value::Array(Array::new(vec![
+            Value::BasicValue(BasicValue::Uint64(5)),
+            Value::BasicValue(BasicValue::Uint64(6)),
+        ]));
+        // The padding between the length and the first element is not included in the length
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![
+            16,0,0,0, 0,0,0,0,
+            5,0,0,0,0,0,0,0,
+            6,0,0,0,0,0,0,0,
+        ]);
+
+        let v = Value::Array(Array::new_with_sig(vec![], "at".to_string()));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![0,0,0,0, 0,0,0,0]);
+
+        let v = Value::Array(Array::new_with_sig(vec![Value::BasicValue(BasicValue::Uint32(5))], "at".to_string()));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::BadSignature));
+    }
+
+    #[test]
+    fn test_dictionary () {
+        let v = Value::Dictionary(Dictionary::new(vec![
+            DictEntry {
+                key: BasicValue::String("a".to_string()),
+                value: Value::BasicValue(BasicValue::Byte(1)),
+            },
+        ]));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![
+            7,0,0,0, 0,0,0,0,
+            1,0,0,0, b'a',0, 1,
+        ]);
+
+        let v = Value::Dictionary(Dictionary::new_with_sig(vec![
+            DictEntry {
+                key: BasicValue::String("a".to_string()),
+                value: Value::BasicValue(BasicValue::Byte(1)),
+            },
+        ], "a{sv}".to_string()));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::BadSignature));
+    }
+
+    #[test]
+    fn test_variant () {
+        let v = Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(7)), "u"));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little).unwrap(), vec![1,b'u',0,0, 7,0,0,0]);
+
+        let v = Value::Variant(Variant::new(Value::BasicValue(BasicValue::Uint32(7)), "s"));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::BadSignature));
+    }
+
+    #[test]
+    fn test_bad_string () {
+        let v = Value::BasicValue(BasicValue::String("a\0b".to_string()));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::StringContainsNul));
+    }
+
+    #[test]
+    fn test_bad_path_and_signature () {
+        let v = Value::BasicValue(BasicValue::ObjectPath(Path("foo".to_string())));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::InvalidObjectPath));
+
+        let v = Value::BasicValue(BasicValue::Signature(Signature("a{vs}".to_string())));
+        assert_eq!(WireEncoder::encode(&v, Endianness::Little), Err(WireError::BadSignature));
+    }
+
+    #[test]
+    fn test_decode_basic () {
+        let v = WireDecoder::decode(&[4,3,2,1], 0, "u", Endianness::Little).unwrap();
+        assert_eq!(v, Value::BasicValue(BasicValue::Uint32(0x01020304)));
+
+        let v = WireDecoder::decode(&[0xff,0,0,0,b'f',b'o',b'o',0], 4, "s", Endianness::Little);
+        assert_eq!(v, Err(WireError::UnexpectedEof));
+
+        let v = WireDecoder::decode(&[0xff,0,0,0, 4,0,0,0,b'/',b'f',b'o',b'o',0], 4, "o", Endianness::Little).unwrap();
+        assert_eq!(v, Value::BasicValue(BasicValue::ObjectPath(Path("/foo".to_string()))));
+
+        let v = WireDecoder::decode(&[3,0,0,0,b'f',b'o',b'o',0], 0, "o", Endianness::Little);
+        assert_eq!(v, Err(WireError::InvalidObjectPath));
+
+        let v = WireDecoder::decode(&[2,0,0,0], 0, "b", Endianness::Little);
+        assert_eq!(v, Err(WireError::InvalidBoolean));
+
+        let v = WireDecoder::decode(&[], 8, "y", Endianness::Little);
+        assert_eq!(v, Err(WireError::UnexpectedEof));
+
+        let v = WireDecoder::decode(&[0,0,0,0], 8, "ay", Endianness::Little);
+        assert_eq!(v, Err(WireError::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_decode_padding () {
+        let v = WireDecoder::decode(&[0,1,0,0,7,0,0,0], 1, "u", Endianness::Little);
+        assert_eq!(v, Err(WireError::NonZeroPadding));
+
+        let v = WireDecoder::decode(&[0,0,0,0,7,0,0,0], 1, "u", Endianness::Little).unwrap();
+        assert_eq!(v, Value::BasicValue(BasicValue::Uint32(7)));
+    }
+
+    #[test]
+    fn test_round_trip () {
+        let values = vec![
+            Value::Struct(Struct {
+                objects: vec![
+                    Value::BasicValue(BasicValue::Byte(1)),
+                    Value::BasicValue(BasicValue::Double(2.5)),
+                    Value::BasicValue(BasicValue::Int16(-3)),
+                ],
+                signature: Signature("(ydn)".to_string())
+            }),
+            Value::Array(Array::new(vec![
+                Value::BasicValue(BasicValue::Uint64(5)),
+                Value::BasicValue(BasicValue::Uint64(6)),
+            ])),
+            Value::Array(Array::new_with_sig(vec![], "a(ii)".to_string())),
+            Value::Dictionary(Dictionary::new(vec![
+                DictEntry {
+                    key: BasicValue::String("a".to_string()),
+                    value: Value::Variant(Variant::new(Value::BasicValue(BasicValue::Int64(-1)), "x")),
+                },
+                DictEntry {
+                    key: BasicValue::String("b".to_string()),
+                    value: Value::Variant(Variant::new(Value::BasicValue(BasicValue::Boolean(true)), "b")),
+                },
+            ])),
+        ];
+
+        for v in values {
+            for &endian in &[Endianness::Little, Endianness::Big] {
+                let bytes = WireEncoder::encode(&v, endian).unwrap();
+                let mut decoder = WireDecoder::new(&bytes, 0, endian);
+                assert_eq!(decoder.read_value(v.get_signature()).unwrap(), v);
+                assert_eq!(decoder.position(), bytes.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_bad_signature () {
+        assert_eq!(WireDecoder::decode(&[0,0,0,0], 0, "uu", Endianness::Little), Err(WireError::BadSignature));
+        assert_eq!(WireDecoder::decode(&[0,0,0,0], 0, "(u}", Endianness::Little), Err(WireError::BadSignature));
+        assert_eq!(WireDecoder::decode(&[0,0,0,0], 0, "a", Endianness::Little), Err(WireError::BadSignature));
+    }
+
+    #[test]
+    fn test_decode_too_deep () {
+        // A variant containing a variant containing a variant...
+        let mut bytes = Vec::new();
+        for _ in 0..MAX_DEPTH+1 {
+            bytes.extend_from_slice(&[1,b'v',0]);
+        }
+        assert_eq!(WireDecoder::decode(&bytes, 0, "v", Endianness::Little), Err(WireError::TooDeep));
+    }
+}